@@ -1,149 +1,900 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::{self, File},
     io::Write,
     path::Path,
-    sync::mpsc,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use futures::StreamExt;
 
-use crate::web;
+use crate::{error::Error, lcu, lcu::item_sets, lcu::item_sets::ItemSet, web};
 
-pub async fn save_build(path: String, data: &web::ItemBuild) -> Result<()> {
+pub mod cancel;
+pub mod layout;
+pub mod manifest;
+pub mod patch;
+pub mod progress;
+pub mod report;
+pub mod snapshot;
+pub mod staging;
+
+use cancel::CancelToken;
+use layout::Target;
+use manifest::{AppliedSource, Manifest, ManifestEntry};
+use progress::{Progress, ProgressSender};
+use report::ChampResult;
+
+/// Writes one item build and returns the number of bytes written.
+pub async fn save_build(path: String, data: &web::ItemBuild) -> Result<u64> {
     let path = Path::new(&path);
-    let prefix = path.parent().unwrap();
-    fs::create_dir_all(prefix).unwrap();
+    let prefix = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .ok_or_else(|| anyhow!("no folder in build path {:?}", path))?;
+    fs::create_dir_all(prefix).with_context(|| format!("create {}", prefix.display()))?;
 
-    let mut f = File::create(&path)?;
+    let mut f = File::create(path).with_context(|| format!("create {}", path.display()))?;
     let buf = serde_json::to_string(&data)?;
-    f.write_all(&buf[..].as_bytes())?;
-    Ok(())
+    f.write_all(buf.as_bytes())?;
+    Ok(buf.len() as u64)
+}
+
+/// Writes the builds of one source. Returns one result per champion, and a
+/// manifest entry per file written. Files in `edited` belong to the user and
+/// are skipped.
+#[allow(clippy::too_many_arguments)]
+pub async fn save_source_builds(
+    path: &str,
+    target: Target,
+    source: &str,
+    champ_names: &[String],
+    champs: &HashMap<String, Vec<web::ChampData>>,
+    edited: &[String],
+    progress: &ProgressSender,
+    cancel: &CancelToken,
+) -> (Vec<ChampResult>, Vec<ManifestEntry>) {
+    let mut results = vec![];
+    let mut entries = vec![];
+
+    for champ_name in champ_names.iter() {
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        let data = match champs.get(champ_name) {
+            Some(data) if !data.is_empty() => data,
+            _ => {
+                println!("failed: {} {}", source, champ_name);
+                progress.send(Progress::ChampFailed {
+                    source: source.to_string(),
+                    champ_name: champ_name.clone(),
+                });
+                results.push(ChampResult::failed(
+                    source,
+                    champ_name,
+                    "no data in this source".to_string(),
+                ));
+                continue;
+            }
+        };
+
+        let mut failure = None;
+        let mut bytes = 0;
+        for (idx, i) in data.iter().enumerate() {
+            for (iidx, build) in i.item_builds.iter().enumerate() {
+                let rel = format!(
+                    "{dir}/{source}-{champ_name}-{idx}-{iidx}.json",
+                    dir = layout::champ_dir(target, champ_name),
+                    source = source,
+                    champ_name = champ_name,
+                    idx = idx,
+                    iidx = iidx
+                );
+                if edited.contains(&rel) {
+                    println!("keeping edited build {}", rel);
+                    continue;
+                }
+                let p = format!("{path}/{rel}", path = path, rel = rel);
+                let ret = match save_build(p.clone(), build).await {
+                    Ok(n) => manifest::hash_file(Path::new(&p)).map(|hash| (n, hash)),
+                    Err(e) => Err(e),
+                };
+                match ret {
+                    Ok((n, hash)) => {
+                        bytes += n;
+                        entries.push(ManifestEntry {
+                            source: source.to_string(),
+                            champ_name: champ_name.clone(),
+                            version: i.version.clone(),
+                            path: rel,
+                            hash,
+                        });
+                    }
+                    Err(e) => {
+                        println!("save err: {:?}", e);
+                        if failure.is_none() {
+                            failure = Some(format!("{:#}", e));
+                        }
+                    }
+                }
+            }
+        }
+
+        match failure {
+            None => {
+                progress.send(Progress::ChampDone {
+                    source: source.to_string(),
+                    champ_name: champ_name.clone(),
+                    bytes,
+                });
+                results.push(ChampResult::done(source, champ_name));
+            }
+            Some(reason) => {
+                progress.send(Progress::ChampFailed {
+                    source: source.to_string(),
+                    champ_name: champ_name.clone(),
+                });
+                results.push(ChampResult::failed(source, champ_name, reason));
+            }
+        }
+    }
+
+    (results, entries)
+}
+
+/// How an apply writes its builds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApplyOptions {
+    /// Keep builds written by earlier applies.
+    pub keep_old: bool,
+    pub target: Target,
+    /// Leave out sources whose data is for another patch than the installed
+    /// game, instead of only warning about them.
+    pub skip_other_patches: bool,
+    /// Champions to apply per source, used to retry the failures of an
+    /// earlier apply. Empty applies every champion of every source.
+    pub only_champs: BTreeMap<String, Vec<String>>,
+    /// Snapshot file to take builds from instead of the network, see
+    /// `snapshot::Snapshot`.
+    pub snapshot: Option<String>,
+    /// Credentials of the running client when they are already known. An
+    /// apply only looks for the lockfile itself, never for elevation.
+    pub lcu_auth: Option<lcu::LcuAuth>,
+}
+
+impl ApplyOptions {
+    /// Champions of `source` this apply writes, out of `all`.
+    pub fn champ_names(&self, source: &str, all: &[String]) -> Vec<String> {
+        if self.only_champs.is_empty() {
+            return all.to_vec();
+        }
+        match self.only_champs.get(source) {
+            Some(only) => all.iter().filter(|c| only.contains(c)).cloned().collect(),
+            None => vec![],
+        }
+    }
 }
 
 pub async fn apply_builds(
     sources: Vec<String>,
     path: String,
-    keep_old: bool,
-) -> Result<Vec<(bool, String, String)>> {
-    let path_exists = Path::new(&path).exists();
-    if path_exists && !keep_old {
-        fs::remove_dir_all(path.clone())?;
-        println!("emptied old dir: {}", path);
+    options: ApplyOptions,
+) -> Result<Vec<ChampResult>> {
+    apply_builds_with_progress(
+        sources,
+        path,
+        options,
+        ProgressSender::none(),
+        CancelToken::new(),
+    )
+    .await
+}
+
+/// Removes the old builds listed in `manifest` once the new ones are
+/// written. A source spec in `sources` without a single build written keeps its
+/// old builds, so a failed fetch never leaves it with nothing.
+fn remove_replaced(
+    dir: &Path,
+    manifest: &mut Manifest,
+    sources: &[String],
+    results: &[ChampResult],
+    written: &[ManifestEntry],
+) -> Result<usize> {
+    let failed: Vec<String> = sources
+        .iter()
+        .map(|spec| parse_source_spec(spec).0)
+        .filter(|s| !results.iter().any(|r| r.ok && r.source == *s))
+        .collect();
+    let (kept, old) = std::mem::take(&mut manifest.entries)
+        .into_iter()
+        .partition(|e| failed.contains(&e.source) || written.iter().any(|w| w.path == e.path));
+    manifest.entries = kept;
+
+    let old = Manifest {
+        entries: old,
+        ..Manifest::default()
+    };
+    old.remove_files(dir)
+}
+
+/// Applies builds to `path`. Builds are written to a staging dir first, so
+/// a failed or cancelled apply never touches the builds already in `path`.
+/// Without `keep_old` only the files listed in the previous manifest are
+/// removed, item sets made by hand stay where they are. `Target::Lcu` sends
+/// them to the client instead. When every champion fails nothing is written,
+/// and the failed results are returned so they can be retried.
+pub async fn apply_builds_with_progress(
+    sources: Vec<String>,
+    path: String,
+    options: ApplyOptions,
+    progress: ProgressSender,
+    cancel: CancelToken,
+) -> Result<Vec<ChampResult>> {
+    let auth = options.lcu_auth.clone().or_else(|| lcu::find_client(&path));
+    let installed = patch::installed_patch(&path, auth.as_ref()).await;
+    println!("installed game version: {:?}", installed);
+
+    if options.target == Target::Lcu {
+        return push_builds(sources, auth, options, installed, progress, cancel).await;
     }
 
-    let v = web::fetch_lol_version_list().await?;
-    let latest_version = v.first().unwrap();
-    if latest_version.chars().count() == 0 {
-        panic!("fetch lol version failed")
+    let root = layout::resolve(Path::new(&path), options.target)?;
+    println!("writing builds to {}", root.display());
+
+    let job_cancel = cancel.clone();
+    staging::with_staging(&root, &cancel, |staging| async move {
+        let mut manifest = Manifest::load(&staging);
+        let dir = staging.to_string_lossy().to_string();
+        let fetched = load_builds(sources.clone(), installed, &options, &progress).await?;
+        let (results, entries) =
+            write_builds(fetched, dir, &options, &mut manifest, progress, job_cancel).await;
+        if !results.iter().any(|r| r.ok) {
+            // fails the job, so the folder is left as it was
+            return Err(nothing_written(&results));
+        }
+
+        if !options.keep_old {
+            let removed = remove_replaced(&staging, &mut manifest, &sources, &results, &entries)?;
+            println!("removed {} old builds", removed);
+        }
+        manifest.merge(entries);
+        manifest.save(&staging)?;
+        Ok(results)
+    })
+    .await
+    .or_else(|e| match e.downcast::<NothingWritten>() {
+        Ok(NothingWritten(results)) => Ok(results),
+        Err(e) => Err(e),
+    })
+}
+
+/// Splits `op.gg@1.2.3` into the source name and a pinned package version,
+/// a source without a version follows `latest`.
+pub fn parse_source_spec(spec: &str) -> (String, String) {
+    match spec.split_once('@') {
+        Some((source, version)) if !version.is_empty() => (source.to_string(), version.to_string()),
+        Some((source, _)) => (source.to_string(), "latest".to_string()),
+        None => (spec.to_string(), "latest".to_string()),
     }
+}
+
+/// Builds of every source, fetched before anything is written.
+struct FetchedBuilds {
+    champ_list: web::ChampListResp,
+    champ_names: Vec<String>,
+    sources: Vec<(String, Result<web::SourcePackage>)>,
+}
+
+/// Checks a source's data against the installed game. A mismatch is
+/// reported, and only fails the source with `skip`.
+fn check_patch(
+    source: &str,
+    package: &web::SourcePackage,
+    installed: Option<&str>,
+    skip: bool,
+    progress: &ProgressSender,
+) -> Result<()> {
+    let installed = match installed {
+        Some(installed) if !package.patch.is_empty() => installed,
+        _ => return Ok(()),
+    };
+    if patch::same_patch(&package.patch, installed) {
+        return Ok(());
+    }
+
+    println!(
+        "{} is for patch {}, the game is on {}",
+        source, package.patch, installed
+    );
+    progress.send(Progress::PatchMismatch {
+        source: source.to_string(),
+        patch: package.patch.clone(),
+        installed: installed.to_string(),
+        skipped: skip,
+    });
+    if skip {
+        return Err(anyhow!(
+            "{} is for patch {}, the game is on {}",
+            source,
+            package.patch,
+            installed
+        ));
+    }
+    Ok(())
+}
 
-    let champ_list = web::fetch_champ_list(latest_version.to_string()).await?;
-    let mut tasks = vec![];
+/// DDragon version to take champion data from, see `patch::ddragon_version`.
+fn pick_lol_version(versions: &[String], installed: Option<&str>) -> Result<String> {
+    match patch::ddragon_version(versions, installed) {
+        Some(v) if !v.is_empty() => Ok(v.clone()),
+        _ => Err(Error::Decode("no lol version in versions.json".to_string()).into()),
+    }
+}
 
-    let (tx, rx) = mpsc::channel();
+fn send_planned(
+    sources: &[String],
+    champ_names: &[String],
+    options: &ApplyOptions,
+    progress: &ProgressSender,
+) {
+    let total = sources
+        .iter()
+        .map(|spec| {
+            let (source, _) = parse_source_spec(spec);
+            options.champ_names(&source, champ_names).len()
+        })
+        .sum();
+    progress.send(Progress::Planned { total });
+}
 
-    for (champ_name, _champ_info) in champ_list.data.into_iter() {
-        for source in sources.iter() {
-            let source = source.clone();
-            let champ_name = champ_name.clone();
-            let npm_name = format!("@champ-r/{}", source);
-            let path = path.clone();
+/// Reports a source that is ready to be written and checks it against the
+/// installed game.
+fn accept_package(
+    source: &str,
+    package: web::SourcePackage,
+    installed: Option<&str>,
+    skip_other_patches: bool,
+    progress: &ProgressSender,
+) -> Result<web::SourcePackage> {
+    println!(
+        "fetched {}@{}, patch {}",
+        source, package.version, package.patch
+    );
+    progress.send(Progress::SourceFetched {
+        source: source.to_string(),
+        version: package.version.clone(),
+        patch: package.patch.clone(),
+    });
+    check_patch(source, &package, installed, skip_other_patches, progress)?;
+    Ok(package)
+}
 
-            let tx = tx.clone();
+/// Builds from the snapshot in `options` when there is one, from the
+/// network otherwise.
+async fn load_builds(
+    sources: Vec<String>,
+    installed: Option<String>,
+    options: &ApplyOptions,
+    progress: &ProgressSender,
+) -> Result<FetchedBuilds> {
+    match &options.snapshot {
+        Some(file) => {
+            let snapshot = snapshot::Snapshot::read(Path::new(file))?;
+            println!("applying from snapshot {}", file);
+            Ok(snapshot.into_fetched(sources, installed.as_deref(), options, progress))
+        }
+        None => fetch_builds(sources, installed, options, progress).await,
+    }
+}
 
-            tasks.push(async move {
-                let resp =
-                    web::fetch_champ_detail(npm_name, "latest".to_string(), champ_name.to_string())
-                        .await;
+/// `sources` are source specs, see `parse_source_spec`. `installed` is the
+/// game version, champion data is taken from its patch when known.
+async fn fetch_builds(
+    sources: Vec<String>,
+    installed: Option<String>,
+    options: &ApplyOptions,
+    progress: &ProgressSender,
+) -> Result<FetchedBuilds> {
+    let v = web::fetch_lol_version_list().await?;
+    let lol_version = pick_lol_version(&v, installed.as_deref())?;
 
-                let data = match resp {
-                    Ok(data) => match data {
-                        Some(data) => data,
-                        _ => vec![],
-                    },
-                    _ => vec![],
-                };
+    let champ_list = web::fetch_champ_list(lol_version).await?;
+    let dir_names = layout::champ_dir_names(&champ_list);
+    let champ_names: Vec<String> = champ_list.data.keys().cloned().collect();
+    send_planned(&sources, &champ_names, options, progress);
 
-                if data.len() == 0 {
-                    tx.send((false, source.clone(), champ_name.clone()))
-                        .unwrap();
-                    println!("failed: {} {}", source, champ_name);
-                }
+    let skip_other_patches = options.skip_other_patches;
+    let tasks = sources.into_iter().map(|spec| {
+        let dir_names = dir_names.clone();
+        let champ_names = champ_names.clone();
+        let progress = progress.clone();
+        let installed = installed.clone();
+        async move {
+            let (source, _) = parse_source_spec(&spec);
+            let package = match crate::sources::resolve(&spec) {
+                Ok(provider) => provider.package(&champ_names).await,
+                Err(e) => Err(e),
+            };
+            let ret = package.and_then(|mut package| {
+                package.champs = layout::rename_champs(package.champs, &dir_names);
+                let installed = installed.as_deref();
+                accept_package(&source, package, installed, skip_other_patches, &progress)
+            });
+            (source, ret)
+        }
+    });
+    let sources = futures::stream::iter(tasks)
+        .buffered(4)
+        .collect::<Vec<_>>()
+        .await;
 
-                for (idx, i) in data.iter().enumerate() {
-                    for (iidx, build) in i.item_builds.iter().enumerate() {
-                        let p = format!(
-                            "{path}/{champ_name}/{source}-{champ_name}-{idx}-{iidx}.json",
-                            path = path,
-                            source = source,
-                            champ_name = champ_name,
-                            idx = idx,
-                            iidx = iidx
-                        );
-                        match save_build(p, build).await {
-                            Ok(_) => {
-                                // println!("finished: [{}] {}", source, champ_name);
-                                tx.send((true, source.clone(), champ_name.clone())).unwrap();
-                            }
-                            Err(e) => {
-                                println!("save err: {:?}", e);
-                                tx.send((false, source.clone(), champ_name.clone()))
-                                    .unwrap();
-                            }
-                        }
-                    }
-                }
+    Ok(FetchedBuilds {
+        champ_list,
+        champ_names,
+        sources,
+    })
+}
+
+/// Marks every champion of a source that could not be fetched as failed.
+fn source_failed(
+    source: &str,
+    champ_names: &[String],
+    err: &anyhow::Error,
+    progress: &ProgressSender,
+) -> Vec<ChampResult> {
+    println!("fetch source failed: {} {:?}", source, err);
+    let reason = format!("{:#}", err);
+    champ_names
+        .iter()
+        .map(|champ_name| {
+            progress.send(Progress::ChampFailed {
+                source: source.to_string(),
+                champ_name: champ_name.clone(),
             });
+            ChampResult::failed(source, champ_name, reason.clone())
+        })
+        .collect()
+}
+
+/// An apply where every champion failed. Keeps the results, so the
+/// failures can still be listed and retried.
+#[derive(Debug)]
+struct NothingWritten(Vec<ChampResult>);
+
+impl std::fmt::Display for NothingWritten {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.iter().find(|r| !r.reason.is_empty()) {
+            Some(r) => write!(
+                f,
+                "no builds were written, {} {}: {}",
+                r.source, r.champ_name, r.reason
+            ),
+            None => write!(f, "no builds were written"),
         }
     }
+}
 
-    futures::stream::iter(tasks)
-        .buffer_unordered(10)
-        .collect::<Vec<()>>()
-        .await;
+impl std::error::Error for NothingWritten {}
 
-    drop(tx);
+/// Error for an apply where every champion failed, with the first reason.
+pub fn nothing_written(results: &[ChampResult]) -> anyhow::Error {
+    NothingWritten(results.to_vec()).into()
+}
 
-    let mut results: Vec<(bool, String, String)> = vec![];
-    for r in rx {
-        if r.0 == false {
+fn log_results(results: &[ChampResult]) {
+    for r in results.iter() {
+        if !r.ok {
             println!("{:?}", r);
         }
-        results.push(r);
     }
     println!("all {}", results.len());
+}
 
+/// Writes every source to `path` and returns the manifest entries of the
+/// files written. Sources with at least one build written are recorded in
+/// `manifest`.
+async fn write_builds(
+    fetched: FetchedBuilds,
+    path: String,
+    options: &ApplyOptions,
+    manifest: &mut Manifest,
+    progress: ProgressSender,
+    cancel: CancelToken,
+) -> (Vec<ChampResult>, Vec<ManifestEntry>) {
+    let edited = manifest.edited.clone();
+    let mut results = vec![];
+    let mut entries = vec![];
+    for (source, ret) in fetched.sources.iter() {
+        let champ_names = options.champ_names(source, &fetched.champ_names);
+        match ret {
+            Ok(package) => {
+                let (r, e) = save_source_builds(
+                    &path,
+                    options.target,
+                    source,
+                    &champ_names,
+                    &package.champs,
+                    &edited,
+                    &progress,
+                    &cancel,
+                )
+                .await;
+                if r.iter().any(|r| r.ok) {
+                    manifest.record_source(AppliedSource {
+                        source: source.clone(),
+                        version: package.version.clone(),
+                        patch: package.patch.clone(),
+                    });
+                }
+                results.extend(r);
+                entries.extend(e);
+            }
+            Err(e) => results.extend(source_failed(source, &champ_names, e, &progress)),
+        }
+    }
+
+    log_results(&results);
+    (results, entries)
+}
+
+/// Converts the builds of one source into LCU item sets. Returns one result
+/// per champion, like `save_source_builds`.
+pub fn source_item_sets(
+    source: &str,
+    champ_names: &[String],
+    champs: &HashMap<String, Vec<web::ChampData>>,
+    champ_list: &web::ChampListResp,
+    progress: &ProgressSender,
+) -> (Vec<ChampResult>, Vec<ItemSet>) {
+    let mut results = vec![];
+    let mut sets = vec![];
+
+    for champ_name in champ_names.iter() {
+        let champion_id = champ_list
+            .data
+            .get(champ_name)
+            .and_then(|c| c.key.parse::<u32>().ok());
+        let (data, champion_id) = match (champs.get(champ_name), champion_id) {
+            (Some(data), Some(id)) if !data.is_empty() => (data, id),
+            (_, id) => {
+                println!("failed: {} {}", source, champ_name);
+                progress.send(Progress::ChampFailed {
+                    source: source.to_string(),
+                    champ_name: champ_name.clone(),
+                });
+                let reason = match id {
+                    Some(_) => "no data in this source",
+                    None => "unknown champion id",
+                };
+                results.push(ChampResult::failed(source, champ_name, reason.to_string()));
+                continue;
+            }
+        };
+
+        let mut bytes = 0;
+        for (idx, i) in data.iter().enumerate() {
+            for (iidx, build) in i.item_builds.iter().enumerate() {
+                let uid = item_sets::make_uid(source, champ_name, idx, iidx);
+                let set = item_sets::to_item_set(uid, champion_id, build);
+                bytes += serde_json::to_vec(&set)
+                    .map(|b| b.len() as u64)
+                    .unwrap_or(0);
+                sets.push(set);
+            }
+        }
+
+        progress.send(Progress::ChampDone {
+            source: source.to_string(),
+            champ_name: champ_name.clone(),
+            bytes,
+        });
+        results.push(ChampResult::done(source, champ_name));
+    }
+
+    (results, sets)
+}
+
+/// Pushes builds to the running League client as account item sets instead
+/// of writing files.
+async fn push_builds(
+    sources: Vec<String>,
+    auth: Option<lcu::LcuAuth>,
+    options: ApplyOptions,
+    installed: Option<String>,
+    progress: ProgressSender,
+    cancel: CancelToken,
+) -> Result<Vec<ChampResult>> {
+    let auth =
+        auth.ok_or_else(|| Error::LcuUnreachable("league client not connected".to_string()))?;
+    let client = lcu::LcuClient::new(&auth)?;
+
+    let fetching = load_builds(sources, installed, &options, &progress);
+    let fetched = tokio::select! {
+        ret = fetching => ret?,
+        _ = cancel.cancelled() => return Err(anyhow!("apply cancelled")),
+    };
+
+    let mut results = vec![];
+    let mut sets = vec![];
+    for (source, ret) in fetched.sources.iter() {
+        let champ_names = options.champ_names(source, &fetched.champ_names);
+        match ret {
+            Ok(package) => {
+                let (r, s) = source_item_sets(
+                    source,
+                    &champ_names,
+                    &package.champs,
+                    &fetched.champ_list,
+                    &progress,
+                );
+                results.extend(r);
+                sets.extend(s);
+            }
+            Err(e) => results.extend(source_failed(source, &champ_names, e, &progress)),
+        }
+    }
+
+    log_results(&results);
+    if !results.iter().any(|r| r.ok) {
+        return Ok(results);
+    }
+    // the client takes every set in one request, so there is nothing to
+    // roll back once it has been sent
+    if cancel.is_cancelled() {
+        return Err(anyhow!("apply cancelled"));
+    }
+    item_sets::push_item_sets(&client, &sets, options.keep_old).await?;
     Ok(results)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn parse_spec() {
+        let spec = |source: &str, version: &str| (source.to_string(), version.to_string());
+        assert_eq!(parse_source_spec("op.gg"), spec("op.gg", "latest"));
+        assert_eq!(
+            parse_source_spec("op.gg-aram@1.0.1"),
+            spec("op.gg-aram", "1.0.1")
+        );
+        assert_eq!(parse_source_spec("op.gg@"), spec("op.gg", "latest"));
+    }
 
     #[tokio::test]
-    async fn save_build() {
-        let sources = vec![
-            "op.gg-aram".to_string(),
-            "op.gg".to_string(),
-            "lolalytics".to_string(),
-            "lolalytics-aram".to_string(),
-        ];
-        let folder = "../.json".to_string();
-        let keep_old = false;
+    async fn unwritable_build_dir() {
+        let dir = temp_dir("champr_unwritable");
+        // a file where the champion folder should go
+        fs::write(dir.join("Annie"), "").unwrap();
+        let folder = dir.to_str().unwrap().to_string();
+
+        assert!(super::save_build(String::new(), &web::ItemBuild::default())
+            .await
+            .is_err());
+        let err = super::save_build(
+            format!("{}/Annie/op.gg-Annie-0-0.json", folder),
+            &web::ItemBuild::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            crate::error::classify(&err),
+            Error::Fs(_) | Error::Permission(_)
+        ));
+
+        let mut champs = HashMap::new();
+        for champ_name in ["Annie", "Zed"] {
+            champs.insert(
+                champ_name.to_string(),
+                vec![web::ChampData {
+                    item_builds: vec![web::ItemBuild::default()],
+                    ..web::ChampData::default()
+                }],
+            );
+        }
+        let champ_names = vec!["Annie".to_string(), "Zed".to_string()];
+        let (results, entries) = save_source_builds(
+            &folder,
+            Target::Flat,
+            "op.gg",
+            &champ_names,
+            &champs,
+            &[],
+            &ProgressSender::none(),
+            &CancelToken::new(),
+        )
+        .await;
+        assert!(!results[0].ok);
+        assert!(results[0].reason.contains("Annie"));
+        assert!(results[1].ok);
+        assert_eq!(entries.len(), 1);
+
+        // a build the user edited is not written over
+        let edited = vec![entries[0].path.clone()];
+        let (results, entries) = save_source_builds(
+            &folder,
+            Target::Flat,
+            "op.gg",
+            &champ_names[1..],
+            &champs,
+            &edited,
+            &ProgressSender::none(),
+            &CancelToken::new(),
+        )
+        .await;
+        assert!(results[0].ok);
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn missing_lol_version() {
+        let versions = vec!["12.10.1".to_string()];
+        assert_eq!(pick_lol_version(&versions, None).unwrap(), "12.10.1");
+        assert!(pick_lol_version(&[], None).is_err());
+        assert!(pick_lol_version(&["".to_string()], Some("12.10.1")).is_err());
+    }
+
+    #[test]
+    fn failed_source_reasons() {
+        let champ_names = vec!["Annie".to_string(), "Zed".to_string()];
+        let err = anyhow::Error::new(Error::HttpStatus {
+            url: "Registry:/@champ-r/op.gg/latest".to_string(),
+            status: 404,
+        });
+        let results = source_failed("op.gg", &champ_names, &err, &ProgressSender::none());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| !r.ok && r.reason.contains("404")));
+        assert!(nothing_written(&results)
+            .to_string()
+            .contains("op.gg Annie"));
+    }
+
+    #[test]
+    fn only_listed_champs() {
+        let all = vec!["Annie".to_string(), "Zed".to_string()];
+        let mut options = ApplyOptions::default();
+        assert_eq!(options.champ_names("op.gg", &all), all);
+
+        options
+            .only_champs
+            .insert("op.gg".to_string(), vec!["Zed".to_string()]);
+        assert_eq!(options.champ_names("op.gg", &all), vec!["Zed".to_string()]);
+        assert!(options.champ_names("lolalytics", &all).is_empty());
+    }
 
-        println!(
-            "start: save builds to local, sources: {:?}, keep old items: {}",
-            sources, keep_old
+    #[tokio::test]
+    async fn skip_other_patches() {
+        let package = web::SourcePackage {
+            version: "1.0.2".to_string(),
+            patch: "12.9.1".to_string(),
+            ..web::SourcePackage::default()
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let progress = ProgressSender::new(tx);
+
+        assert!(check_patch("op.gg", &package, None, true, &progress).is_ok());
+        assert!(check_patch("op.gg", &package, Some("12.9.440.1"), true, &progress).is_ok());
+        assert!(check_patch("op.gg", &package, Some("12.10.445.3587"), false, &progress).is_ok());
+        assert!(check_patch("op.gg", &package, Some("12.10.445.3587"), true, &progress).is_err());
+
+        assert!(matches!(
+            rx.recv().await,
+            Some(Progress::PatchMismatch { skipped: false, .. })
+        ));
+        assert!(matches!(
+            rx.recv().await,
+            Some(Progress::PatchMismatch { skipped: true, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn save_builds_from_tarball() {
+        let dir = temp_dir("champr_save_source_builds");
+        let folder = dir.to_str().unwrap().to_string();
+
+        let mut champs = HashMap::new();
+        champs.insert(
+            "Annie".to_string(),
+            vec![web::ChampData {
+                item_builds: vec![web::ItemBuild::default(), web::ItemBuild::default()],
+                ..web::ChampData::default()
+            }],
         );
+        let champ_names = vec!["Annie".to_string(), "Zed".to_string()];
 
-        match apply_builds(sources, folder, keep_old).await {
-            Ok(_) => {
-                println!("all set");
-            }
-            Err(e) => {
-                println!("{:?}", e);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let (results, entries) = save_source_builds(
+            &folder,
+            Target::Game,
+            "op.gg",
+            &champ_names,
+            &champs,
+            &[],
+            &ProgressSender::new(tx),
+            &CancelToken::new(),
+        )
+        .await;
+        assert_eq!(
+            results,
+            vec![
+                ChampResult::done("op.gg", "Annie"),
+                ChampResult::failed("op.gg", "Zed", "no data in this source".to_string()),
+            ]
+        );
+        assert!(Path::new(&folder)
+            .join("Annie/Recommended/op.gg-Annie-0-1.json")
+            .exists());
+        assert!(!Path::new(&folder).join("Zed").exists());
+        assert_eq!(
+            entries.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(),
+            vec![
+                "Annie/Recommended/op.gg-Annie-0-0.json",
+                "Annie/Recommended/op.gg-Annie-0-1.json"
+            ]
+        );
+
+        match rx.recv().await {
+            Some(Progress::ChampDone {
+                champ_name, bytes, ..
+            }) => {
+                assert_eq!(champ_name, "Annie");
+                assert!(bytes > 0);
             }
+            p => panic!("unexpected progress {:?}", p),
         }
+        assert!(matches!(
+            rx.recv().await,
+            Some(Progress::ChampFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn keep_builds_of_failed_source() {
+        let dir = temp_dir("champr_remove_replaced");
+        let write = |source: &str, rel: &str| {
+            fs::create_dir_all(dir.join(rel).parent().unwrap()).unwrap();
+            fs::write(dir.join(rel), "{}").unwrap();
+            ManifestEntry {
+                source: source.to_string(),
+                champ_name: "Annie".to_string(),
+                version: "12.10.1".to_string(),
+                path: rel.to_string(),
+                hash: manifest::hash_file(&dir.join(rel)).unwrap(),
+            }
+        };
+        let mut manifest = Manifest {
+            entries: vec![
+                write("op.gg", "Annie/op.gg-Annie-0-0.json"),
+                write("op.gg", "Annie/op.gg-Annie-0-1.json"),
+                write("lolalytics", "Annie/lolalytics-Annie-0-0.json"),
+            ],
+            ..Manifest::default()
+        };
+        let written = vec![write("op.gg", "Annie/op.gg-Annie-0-0.json")];
+        let results = vec![
+            ChampResult::done("op.gg", "Annie"),
+            ChampResult::failed("lolalytics", "Annie", "timed out".to_string()),
+        ];
+        let sources = vec!["op.gg".to_string(), "lolalytics".to_string()];
+
+        let removed = remove_replaced(&dir, &mut manifest, &sources, &results, &written).unwrap();
+        assert_eq!(removed, 1);
+        assert!(!dir.join("Annie/op.gg-Annie-0-1.json").exists());
+        assert!(dir.join("Annie/op.gg-Annie-0-0.json").exists());
+        assert!(dir.join("Annie/lolalytics-Annie-0-0.json").exists());
+        assert_eq!(
+            manifest
+                .entries
+                .iter()
+                .map(|e| e.path.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Annie/op.gg-Annie-0-0.json",
+                "Annie/lolalytics-Annie-0-0.json"
+            ]
+        );
     }
 }
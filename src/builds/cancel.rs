@@ -0,0 +1,72 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+/// Shared flag used to stop a running apply from the UI.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once `cancel` has been called.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builds::staging, test_support::temp_dir};
+    use std::{fs, time::Duration};
+
+    #[tokio::test]
+    async fn cancel_mid_write_keeps_dir() {
+        let dir = temp_dir("champr_cancel_mid_write");
+        fs::create_dir_all(dir.join("Annie")).unwrap();
+        fs::write(dir.join("Annie").join("old.json"), "{}").unwrap();
+
+        let cancel = CancelToken::new();
+        let canceller = cancel.clone();
+        let ret = staging::with_staging(&dir, &cancel, |staging| async move {
+            fs::create_dir_all(staging.join("Zed"))?;
+            fs::write(staging.join("Zed").join("new.json"), "{}")?;
+            canceller.cancel();
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        })
+        .await;
+
+        assert!(ret.is_err());
+        assert_eq!(
+            fs::read_to_string(dir.join("Annie").join("old.json")).unwrap(),
+            "{}"
+        );
+        assert!(!dir.join("Zed").exists());
+        assert!(!staging::staging_path(&dir).exists());
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::web::ChampListResp;
+
+/// Where an apply writes its item sets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Target {
+    /// `Game/Config/Champions/<Champ>/Recommended/` of a League install.
+    #[default]
+    Game,
+    /// `<dir>/<Champ>/`, straight into the picked folder, e.g. for export.
+    Flat,
+    /// Account item sets of the running League client.
+    Lcu,
+}
+
+impl Target {
+    pub const ALL: [Target; 3] = [Target::Game, Target::Flat, Target::Lcu];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Target::Game => "League folder",
+            Target::Flat => "Export (flat)",
+            Target::Lcu => "League client",
+        }
+    }
+}
+
+fn file_name_is(p: &Path, name: &str) -> bool {
+    p.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.eq_ignore_ascii_case(name))
+        .unwrap_or(false)
+}
+
+fn is_game_dir(p: &Path) -> bool {
+    file_name_is(p, "Game")
+        || p.join("League of Legends.exe").exists()
+        || p.join("League of Legends.app").exists()
+}
+
+/// Finds `Config/Champions` from whatever folder of the install was picked:
+/// the LeagueClient root, its `Game` subfolder, `Config` or `Champions`.
+pub fn champions_dir(dir: &Path) -> Result<PathBuf> {
+    if file_name_is(dir, "Champions") {
+        return Ok(dir.to_path_buf());
+    }
+    if file_name_is(dir, "Config") {
+        return Ok(dir.join("Champions"));
+    }
+    if dir.join("Game").is_dir() {
+        return Ok(dir.join("Game").join("Config").join("Champions"));
+    }
+    if is_game_dir(dir) {
+        return Ok(dir.join("Config").join("Champions"));
+    }
+    Err(anyhow!(
+        "{} is not a League of Legends folder",
+        dir.display()
+    ))
+}
+
+/// Resolves the folder item sets are written under.
+pub fn resolve(dir: &Path, target: Target) -> Result<PathBuf> {
+    match target {
+        Target::Game => champions_dir(dir),
+        Target::Flat => Ok(dir.to_path_buf()),
+        Target::Lcu => Err(anyhow!("item sets are sent to the client, not a folder")),
+    }
+}
+
+/// Folder of a champion's item sets, relative to the resolved root.
+pub fn champ_dir(target: Target, champ_name: &str) -> String {
+    match target {
+        Target::Game => format!("{}/Recommended", champ_name),
+        Target::Flat | Target::Lcu => champ_name.to_string(),
+    }
+}
+
+/// Maps champion ids, names and numeric keys (lowercased) to the folder name
+/// the game uses, e.g. `wukong` and `62` to `MonkeyKing`.
+pub fn champ_dir_names(champ_list: &ChampListResp) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    for c in champ_list.data.values() {
+        names.insert(c.id.to_lowercase(), c.id.clone());
+        names.insert(c.name.to_lowercase(), c.id.clone());
+        names.insert(c.key.clone(), c.id.clone());
+    }
+    names
+}
+
+/// Re-keys builds by the game's folder name, unknown keys are kept as is.
+pub fn rename_champs<T>(
+    champs: HashMap<String, T>,
+    names: &HashMap<String, String>,
+) -> HashMap<String, T> {
+    champs
+        .into_iter()
+        .map(|(k, v)| match names.get(&k.to_lowercase()) {
+            Some(name) => (name.clone(), v),
+            None => (k, v),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::temp_dir, web::ChampInfo};
+    use std::fs;
+
+    #[test]
+    fn detect_install_root() {
+        let dir = temp_dir("champr_layout");
+        let root = dir.join("League of Legends");
+        let game = root.join("Game");
+        fs::create_dir_all(game.join("Config")).unwrap();
+        fs::write(game.join("League of Legends.exe"), "").unwrap();
+        let champions = game.join("Config").join("Champions");
+
+        assert_eq!(champions_dir(&root).unwrap(), champions);
+        assert_eq!(champions_dir(&game).unwrap(), champions);
+        assert_eq!(champions_dir(&game.join("Config")).unwrap(), champions);
+        assert_eq!(champions_dir(&champions).unwrap(), champions);
+        assert!(champions_dir(&dir).is_err());
+        assert_eq!(resolve(&dir, Target::Flat).unwrap(), dir.to_path_buf());
+        assert_eq!(champ_dir(Target::Game, "Annie"), "Annie/Recommended");
+        assert_eq!(champ_dir(Target::Flat, "Annie"), "Annie");
+    }
+
+    #[test]
+    fn map_champ_names() {
+        let mut champ_list = ChampListResp::default();
+        champ_list.data.insert(
+            "MonkeyKing".to_string(),
+            ChampInfo {
+                id: "MonkeyKing".to_string(),
+                key: "62".to_string(),
+                name: "Wukong".to_string(),
+                ..ChampInfo::default()
+            },
+        );
+        let names = champ_dir_names(&champ_list);
+
+        let mut champs = HashMap::new();
+        champs.insert("wukong".to_string(), 1);
+        champs.insert("Unknown".to_string(), 2);
+        let champs = rename_champs(champs, &names);
+        assert_eq!(champs.get("MonkeyKing"), Some(&1));
+        assert_eq!(champs.get("Unknown"), Some(&2));
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const MANIFEST_FILE: &str = "champr-manifest.json";
+
+/// One item set written by an apply. `path` is relative to the apply root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    pub source: String,
+    pub champ_name: String,
+    pub version: String,
+    pub path: String,
+    pub hash: String,
+}
+
+/// Package version a source was last applied from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedSource {
+    pub source: String,
+    pub version: String,
+    pub patch: String,
+}
+
+/// Files ChampR wrote into a folder. Anything not listed here belongs to the
+/// user and is never removed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+    pub sources: Vec<AppliedSource>,
+    /// Files the user edited after an apply wrote them. They belong to the
+    /// user from then on, later applies neither remove nor rewrite them.
+    pub edited: Vec<String>,
+}
+
+pub fn manifest_path(dir: &Path) -> PathBuf {
+    dir.join(MANIFEST_FILE)
+}
+
+/// `dir` joined with a path from the manifest. Absolute paths and `..` are
+/// refused, so a tampered manifest can't point outside `dir`.
+pub fn entry_path(dir: &Path, rel: &str) -> Result<PathBuf> {
+    let rel_path = Path::new(rel);
+    let plain = rel_path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)));
+    if rel.is_empty() || !plain {
+        return Err(anyhow!("invalid path in manifest: {:?}", rel));
+    }
+    Ok(dir.join(rel_path))
+}
+
+/// Removes the folder holding `file` when it is empty now, `dir` itself is
+/// kept.
+pub fn remove_empty_parent(dir: &Path, file: &Path) -> Result<()> {
+    if let Some(parent) = file.parent() {
+        if parent != dir && parent.is_dir() && fs::read_dir(parent)?.next().is_none() {
+            fs::remove_dir(parent)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn hash_file(path: &Path) -> Result<String> {
+    let buf = fs::read(path)?;
+    Ok(format!("{:x}", Sha256::digest(&buf)))
+}
+
+impl Manifest {
+    /// Reads the manifest in `dir`, a missing or unreadable one is empty.
+    pub fn load(dir: &Path) -> Manifest {
+        let p = manifest_path(dir);
+        let content = match fs::read_to_string(&p) {
+            Ok(content) => content,
+            Err(_) => return Manifest::default(),
+        };
+        match serde_json::from_str(&content) {
+            Ok(m) => m,
+            Err(e) => {
+                println!("[manifest] ignoring corrupt {}, {:?}", p.display(), e);
+                Manifest::default()
+            }
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(manifest_path(dir), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Adds `entries`, replacing older entries for the same file.
+    pub fn merge(&mut self, entries: Vec<ManifestEntry>) {
+        self.entries
+            .retain(|old| !entries.iter().any(|e| e.path == old.path));
+        self.entries.extend(entries);
+    }
+
+    /// Hands files edited since they were written over to the user: their
+    /// entries move to `edited`. Entries of missing files or with invalid
+    /// paths are dropped, and so are edited files the user deleted.
+    pub fn release_edited(&mut self, dir: &Path) {
+        for entry in std::mem::take(&mut self.entries) {
+            let p = match entry_path(dir, &entry.path) {
+                Ok(p) => p,
+                Err(e) => {
+                    println!("[manifest] {:?}", e);
+                    continue;
+                }
+            };
+            match hash_file(&p) {
+                Ok(hash) if hash == entry.hash => self.entries.push(entry),
+                Ok(_) => {
+                    println!("[manifest] {} was edited, leaving it alone", p.display());
+                    if !self.edited.contains(&entry.path) {
+                        self.edited.push(entry.path);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+        self.edited
+            .retain(|rel| entry_path(dir, rel).map(|p| p.exists()).unwrap_or(false));
+    }
+
+    pub fn record_source(&mut self, applied: AppliedSource) {
+        self.sources.retain(|s| s.source != applied.source);
+        self.sources.push(applied);
+    }
+
+    /// Removes the listed files from `dir` and returns how many were removed.
+    /// Files edited since they were written are not ours anymore and stay,
+    /// see `release_edited`, and so do champion folders that still hold
+    /// other files.
+    pub fn remove_files(&self, dir: &Path) -> Result<usize> {
+        let mut removed = 0;
+        for entry in self.entries.iter() {
+            let p = entry_path(dir, &entry.path)?;
+            match hash_file(&p) {
+                Ok(hash) if hash == entry.hash => {
+                    fs::remove_file(&p)?;
+                    removed += 1;
+                }
+                _ => continue,
+            }
+            remove_empty_parent(dir, &p)?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn write_entry(dir: &Path, champ_name: &str, file: &str) -> ManifestEntry {
+        let rel = format!("{}/{}", champ_name, file);
+        fs::create_dir_all(dir.join(champ_name)).unwrap();
+        fs::write(dir.join(&rel), "{}").unwrap();
+        ManifestEntry {
+            source: "op.gg".to_string(),
+            champ_name: champ_name.to_string(),
+            version: "12.10.1".to_string(),
+            hash: hash_file(&dir.join(&rel)).unwrap(),
+            path: rel,
+        }
+    }
+
+    #[test]
+    fn remove_only_listed_files() {
+        let dir = temp_dir("champr_manifest_remove");
+
+        let manifest = Manifest {
+            entries: vec![
+                write_entry(&dir, "Annie", "op.gg-Annie-0-0.json"),
+                write_entry(&dir, "Zed", "op.gg-Zed-0-0.json"),
+                write_entry(&dir, "Ahri", "op.gg-Ahri-0-0.json"),
+            ],
+            ..Manifest::default()
+        };
+        manifest.save(&dir).unwrap();
+        assert_eq!(Manifest::load(&dir), manifest);
+
+        // hand made set next to a generated one, and a generated one edited
+        fs::write(dir.join("Annie").join("my-annie.json"), "{}").unwrap();
+        fs::write(dir.join("Ahri").join("op.gg-Ahri-0-0.json"), "{\"a\":1}").unwrap();
+
+        assert_eq!(manifest.remove_files(&dir).unwrap(), 2);
+        assert!(dir.join("Annie").join("my-annie.json").exists());
+        assert!(!dir.join("Annie").join("op.gg-Annie-0-0.json").exists());
+        assert!(!dir.join("Zed").exists());
+        assert!(dir.join("Ahri").join("op.gg-Ahri-0-0.json").exists());
+
+        let mut released = manifest.clone();
+        released.release_edited(&dir);
+        assert!(released.entries.is_empty());
+        assert_eq!(released.edited, vec!["Ahri/op.gg-Ahri-0-0.json"]);
+    }
+
+    #[test]
+    fn refuse_paths_outside_dir() {
+        let dir = temp_dir("champr_manifest_paths");
+        assert!(entry_path(&dir, "Annie/op.gg-Annie-0-0.json").is_ok());
+        assert!(entry_path(&dir, "../outside.json").is_err());
+        assert!(entry_path(&dir, "Annie/../../outside.json").is_err());
+        assert!(entry_path(&dir, "/etc/passwd").is_err());
+        assert!(entry_path(&dir, "").is_err());
+
+        let mut manifest = Manifest {
+            entries: vec![write_entry(&dir, "Annie", "op.gg-Annie-0-0.json")],
+            ..Manifest::default()
+        };
+        manifest.entries[0].path = "../outside.json".to_string();
+        assert!(manifest.remove_files(&dir).is_err());
+    }
+
+    #[test]
+    fn merge_replaces_same_path() {
+        let dir = temp_dir("champr_manifest_merge");
+
+        let mut manifest = Manifest {
+            entries: vec![
+                write_entry(&dir, "Annie", "op.gg-Annie-0-0.json"),
+                write_entry(&dir, "Zed", "op.gg-Zed-0-0.json"),
+            ],
+            ..Manifest::default()
+        };
+        let mut newer = write_entry(&dir, "Annie", "op.gg-Annie-0-0.json");
+        newer.version = "12.11.1".to_string();
+        manifest.merge(vec![newer.clone()]);
+
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(manifest.entries[1], newer);
+
+        for version in ["1.0.1", "1.0.2"] {
+            manifest.record_source(AppliedSource {
+                source: "op.gg".to_string(),
+                version: version.to_string(),
+                patch: "12.10.1".to_string(),
+            });
+        }
+        assert_eq!(manifest.sources.len(), 1);
+        assert_eq!(manifest.sources[0].version, "1.0.2");
+    }
+}
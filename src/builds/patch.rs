@@ -0,0 +1,125 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use super::layout;
+use crate::lcu;
+
+const GAME_VERSION_ENDPOINT: &str = "/lol-patch/v1/game-version";
+const CONTENT_METADATA: &str = "content-metadata.json";
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+struct ContentMetadata {
+    version: String,
+}
+
+/// `12.10.445.3587+branch...` and `12.10.1` both become `12.10`.
+pub fn short_patch(version: &str) -> Option<String> {
+    let mut parts = version.split(['.', '+']);
+    let major = parts.next()?.trim();
+    let minor = parts.next()?.trim();
+    if major.parse::<u32>().is_err() || minor.parse::<u32>().is_err() {
+        return None;
+    }
+    Some(format!("{}.{}", major, minor))
+}
+
+pub fn same_patch(a: &str, b: &str) -> bool {
+    match (short_patch(a), short_patch(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// DDragon version for the installed patch, or the newest one when the
+/// patch is unknown or not on DDragon yet.
+pub fn ddragon_version<'a>(versions: &'a [String], installed: Option<&str>) -> Option<&'a String> {
+    installed
+        .and_then(|p| versions.iter().find(|v| same_patch(v, p)))
+        .or_else(|| versions.first())
+}
+
+/// Reads the game version from `Game/content-metadata.json` of an install.
+pub fn read_content_metadata(lol_dir: &Path) -> Result<String> {
+    let champions = layout::champions_dir(lol_dir)?;
+    let game = champions
+        .parent()
+        .and_then(|config| config.parent())
+        .ok_or_else(|| anyhow!("no game folder above {}", champions.display()))?;
+    let content = fs::read_to_string(game.join(CONTENT_METADATA))?;
+    let metadata: ContentMetadata = serde_json::from_str(&content)?;
+    if metadata.version.is_empty() {
+        return Err(anyhow!("no version in {}", CONTENT_METADATA));
+    }
+    Ok(metadata.version)
+}
+
+/// Version of the installed game, read from the install first. The client
+/// is only asked when `auth` is already known, looking for it could prompt
+/// for elevation on every apply.
+pub async fn installed_patch(lol_dir: &str, auth: Option<&lcu::LcuAuth>) -> Option<String> {
+    if !lol_dir.is_empty() {
+        match read_content_metadata(Path::new(lol_dir)) {
+            Ok(version) => return Some(version),
+            Err(e) => println!("[patch] no version in the install, {:?}", e),
+        }
+    }
+
+    let client = lcu::LcuClient::new(auth?).ok()?;
+    match client.get::<String>(GAME_VERSION_ENDPOINT).await {
+        Ok(version) => Some(version),
+        Err(e) => {
+            println!("[patch] lcu game version failed, {:?}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn compare_patches() {
+        assert_eq!(
+            short_patch("12.10.445.3587+branch.releases-12-10"),
+            Some("12.10".to_string())
+        );
+        assert_eq!(short_patch("12.10.1"), Some("12.10".to_string()));
+        assert_eq!(short_patch("latest"), None);
+        assert!(same_patch("12.10.445.3587", "12.10.1"));
+        assert!(!same_patch("12.9.1", "12.10.1"));
+        assert!(!same_patch("", "12.10.1"));
+
+        let versions = vec!["12.11.1".to_string(), "12.10.1".to_string()];
+        assert_eq!(
+            ddragon_version(&versions, Some("12.10.445.3587")),
+            Some(&versions[1])
+        );
+        assert_eq!(
+            ddragon_version(&versions, Some("12.12.1")),
+            Some(&versions[0])
+        );
+        assert_eq!(ddragon_version(&versions, None), Some(&versions[0]));
+    }
+
+    #[test]
+    fn read_install_version() {
+        let parent = temp_dir("champr_patch");
+        let root = parent.join("League of Legends");
+        let game = root.join("Game");
+        fs::create_dir_all(game.join("Config")).unwrap();
+        fs::write(
+            game.join(CONTENT_METADATA),
+            r#"{"version":"12.10.445.3587+branch.releases-12-10.content.release"}"#,
+        )
+        .unwrap();
+
+        let version = read_content_metadata(&root).unwrap();
+        assert!(same_patch(&version, "12.10.1"));
+        assert!(read_content_metadata(&parent).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use iced::Subscription;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::{apply_builds_with_progress, cancel::CancelToken, report::ChampResult, ApplyOptions};
+use crate::error::ErrorReport;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// Number of (source, champion) pairs the apply is going to write.
+    Planned {
+        total: usize,
+    },
+    /// A source package was downloaded, at this version.
+    SourceFetched {
+        source: String,
+        version: String,
+        patch: String,
+    },
+    /// A source's data is for another patch than the installed game.
+    PatchMismatch {
+        source: String,
+        patch: String,
+        installed: String,
+        skipped: bool,
+    },
+    ChampDone {
+        source: String,
+        champ_name: String,
+        bytes: u64,
+    },
+    ChampFailed {
+        source: String,
+        champ_name: String,
+    },
+}
+
+/// Sending half of a progress channel. Sends never fail, events are dropped
+/// when nobody listens.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressSender(Option<mpsc::UnboundedSender<Progress>>);
+
+impl ProgressSender {
+    pub fn new(tx: mpsc::UnboundedSender<Progress>) -> Self {
+        Self(Some(tx))
+    }
+
+    pub fn none() -> Self {
+        Self(None)
+    }
+
+    pub fn send(&self, p: Progress) {
+        if let Some(tx) = &self.0 {
+            let _ = tx.send(p);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceProgress {
+    pub source: String,
+    pub version: String,
+    pub patch: String,
+    /// Game version, set when it differs from `patch`.
+    pub installed: String,
+    pub done: usize,
+    pub failed: usize,
+}
+
+/// Running totals of an apply, as shown in the window.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgressState {
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+    pub bytes: u64,
+    pub sources: Vec<SourceProgress>,
+}
+
+impl ProgressState {
+    pub fn update(&mut self, p: &Progress) {
+        match p {
+            Progress::Planned { total } => {
+                self.total = *total;
+            }
+            Progress::SourceFetched {
+                source,
+                version,
+                patch,
+            } => {
+                let s = self.source_mut(source);
+                s.version = version.clone();
+                s.patch = patch.clone();
+            }
+            Progress::PatchMismatch {
+                source, installed, ..
+            } => {
+                self.source_mut(source).installed = installed.clone();
+            }
+            Progress::ChampDone { source, bytes, .. } => {
+                self.done += 1;
+                self.bytes += bytes;
+                self.source_mut(source).done += 1;
+            }
+            Progress::ChampFailed { source, .. } => {
+                self.failed += 1;
+                self.source_mut(source).failed += 1;
+            }
+        }
+    }
+
+    fn source_mut(&mut self, source: &str) -> &mut SourceProgress {
+        let idx = match self.sources.iter().position(|s| s.source == source) {
+            Some(idx) => idx,
+            None => {
+                self.sources.push(SourceProgress {
+                    source: source.to_string(),
+                    ..SourceProgress::default()
+                });
+                self.sources.len() - 1
+            }
+        };
+        &mut self.sources[idx]
+    }
+
+    pub fn finished(&self) -> usize {
+        self.done + self.failed
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ApplyEvent {
+    Progress(Progress),
+    Finished(Result<Vec<ChampResult>, ErrorReport>),
+}
+
+enum State {
+    Ready(Vec<String>, String, ApplyOptions, CancelToken),
+    Running(
+        mpsc::UnboundedReceiver<Progress>,
+        JoinHandle<anyhow::Result<Vec<ChampResult>>>,
+    ),
+    Finished,
+}
+
+#[derive(Hash)]
+struct ApplyId(u64);
+
+/// Runs an apply in the background and streams its progress. `id` tells
+/// runs apart, so applying again starts a new run.
+pub fn subscription(
+    id: u64,
+    sources: Vec<String>,
+    path: String,
+    options: ApplyOptions,
+    cancel: CancelToken,
+) -> Subscription<ApplyEvent> {
+    iced_native::subscription::unfold(
+        ApplyId(id),
+        State::Ready(sources, path, options, cancel),
+        |state| async move {
+            match state {
+                State::Ready(sources, path, options, cancel) => {
+                    let (tx, rx) = mpsc::unbounded_channel();
+                    let handle = tokio::spawn(apply_builds_with_progress(
+                        sources,
+                        path,
+                        options,
+                        ProgressSender::new(tx),
+                        cancel,
+                    ));
+                    (None, State::Running(rx, handle))
+                }
+                State::Running(mut rx, handle) => match rx.recv().await {
+                    Some(p) => (Some(ApplyEvent::Progress(p)), State::Running(rx, handle)),
+                    None => {
+                        let ret = match handle.await {
+                            Ok(Ok(results)) => Ok(results),
+                            Ok(Err(e)) => Err(ErrorReport::new("apply builds", &e)),
+                            Err(e) => Err(ErrorReport::new("apply builds", &e.into())),
+                        };
+                        (Some(ApplyEvent::Finished(ret)), State::Finished)
+                    }
+                },
+                State::Finished => futures::future::pending().await,
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_progress() {
+        let mut state = ProgressState::default();
+        let events = [
+            Progress::Planned { total: 3 },
+            Progress::SourceFetched {
+                source: "op.gg".to_string(),
+                version: "1.0.2".to_string(),
+                patch: "12.10.1".to_string(),
+            },
+            Progress::ChampDone {
+                source: "op.gg".to_string(),
+                champ_name: "Annie".to_string(),
+                bytes: 100,
+            },
+            Progress::ChampFailed {
+                source: "op.gg".to_string(),
+                champ_name: "Zed".to_string(),
+            },
+            Progress::ChampDone {
+                source: "lolalytics".to_string(),
+                champ_name: "Annie".to_string(),
+                bytes: 20,
+            },
+        ];
+        for p in events.iter() {
+            state.update(p);
+        }
+
+        assert_eq!(state.total, 3);
+        assert_eq!(state.finished(), 3);
+        assert_eq!(state.bytes, 120);
+        assert_eq!(
+            state.sources,
+            vec![
+                SourceProgress {
+                    source: "op.gg".to_string(),
+                    version: "1.0.2".to_string(),
+                    patch: "12.10.1".to_string(),
+                    done: 1,
+                    failed: 1,
+                    ..SourceProgress::default()
+                },
+                SourceProgress {
+                    source: "lolalytics".to_string(),
+                    done: 1,
+                    ..SourceProgress::default()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{layout::Target, parse_source_spec};
+
+/// Outcome of one champion of one source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChampResult {
+    pub source: String,
+    pub champ_name: String,
+    pub ok: bool,
+    /// Why the champion failed, empty when it didn't.
+    pub reason: String,
+}
+
+impl ChampResult {
+    pub fn done(source: &str, champ_name: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            champ_name: champ_name.to_string(),
+            ok: true,
+            reason: String::new(),
+        }
+    }
+
+    pub fn failed(source: &str, champ_name: &str, reason: String) -> Self {
+        Self {
+            source: source.to_string(),
+            champ_name: champ_name.to_string(),
+            ok: false,
+            reason,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSummary {
+    pub source: String,
+    pub done: usize,
+    pub failed: Vec<String>,
+}
+
+/// Counts results per source, keeping the order sources first appear in.
+pub fn summarize(results: &[ChampResult]) -> Vec<SourceSummary> {
+    let mut summary: Vec<SourceSummary> = vec![];
+    for r in results.iter() {
+        let idx = match summary.iter().position(|s| s.source == r.source) {
+            Some(idx) => idx,
+            None => {
+                summary.push(SourceSummary {
+                    source: r.source.clone(),
+                    done: 0,
+                    failed: vec![],
+                });
+                summary.len() - 1
+            }
+        };
+        if r.ok {
+            summary[idx].done += 1;
+        } else {
+            summary[idx].failed.push(r.champ_name.clone());
+        }
+    }
+    summary
+}
+
+/// Everything an apply did, kept after it finishes so failures can be
+/// looked at, exported and retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyReport {
+    /// Source specs the apply ran with, see `parse_source_spec`.
+    pub sources: Vec<String>,
+    pub path: String,
+    pub target: Target,
+    /// Seconds since the unix epoch.
+    pub finished_at: u64,
+    pub results: Vec<ChampResult>,
+}
+
+impl ApplyReport {
+    pub fn new(
+        sources: Vec<String>,
+        path: String,
+        target: Target,
+        results: Vec<ChampResult>,
+    ) -> Self {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            sources,
+            path,
+            target,
+            finished_at,
+            results,
+        }
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ChampResult> {
+        self.results.iter().filter(|r| !r.ok)
+    }
+
+    /// Failed champions per source, the shape `ApplyOptions::only_champs`
+    /// takes.
+    pub fn failed_champs(&self) -> BTreeMap<String, Vec<String>> {
+        let mut failed: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for r in self.failures() {
+            failed
+                .entry(r.source.clone())
+                .or_default()
+                .push(r.champ_name.clone());
+        }
+        failed
+    }
+
+    /// Specs of the sources that have something to retry, pins included.
+    pub fn retry_sources(&self) -> Vec<String> {
+        let failed = self.failed_champs();
+        self.sources
+            .iter()
+            .filter(|spec| failed.contains_key(&parse_source_spec(spec).0))
+            .cloned()
+            .collect()
+    }
+
+    /// Takes the results of a retry over the entries they replace, results
+    /// of champions that weren't retried stay as they are.
+    pub fn merge_retry(&mut self, results: Vec<ChampResult>) {
+        for r in results {
+            match self
+                .results
+                .iter_mut()
+                .find(|old| old.source == r.source && old.champ_name == r.champ_name)
+            {
+                Some(old) => *old = r,
+                None => self.results.push(r),
+            }
+        }
+    }
+
+    pub fn export(&self, file: &Path) -> Result<()> {
+        fs::write(file, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn make_report() -> ApplyReport {
+        ApplyReport::new(
+            vec!["op.gg@1.0.2".to_string(), "lolalytics".to_string()],
+            "/games/lol".to_string(),
+            Target::Game,
+            vec![
+                ChampResult::done("op.gg", "Annie"),
+                ChampResult::failed("lolalytics", "Annie", "no data".to_string()),
+                ChampResult::failed("op.gg", "Zed", "permission denied".to_string()),
+            ],
+        )
+    }
+
+    #[test]
+    fn summarize_results() {
+        assert_eq!(
+            summarize(&make_report().results),
+            vec![
+                SourceSummary {
+                    source: "op.gg".to_string(),
+                    done: 1,
+                    failed: vec!["Zed".to_string()],
+                },
+                SourceSummary {
+                    source: "lolalytics".to_string(),
+                    done: 0,
+                    failed: vec!["Annie".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn retry_only_failures() {
+        let mut report = make_report();
+        assert_eq!(
+            report.failed_champs().get("op.gg"),
+            Some(&vec!["Zed".to_string()])
+        );
+        assert_eq!(report.retry_sources(), report.sources);
+
+        report.merge_retry(vec![
+            ChampResult::done("lolalytics", "Annie"),
+            ChampResult::failed("op.gg", "Zed", "still failing".to_string()),
+        ]);
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.results[0], ChampResult::done("op.gg", "Annie"));
+        assert!(report.results[1].ok);
+        assert_eq!(report.results[2].reason, "still failing");
+        assert_eq!(report.retry_sources(), vec!["op.gg@1.0.2".to_string()]);
+    }
+
+    #[test]
+    fn export_json() {
+        let report = make_report();
+        let dir = temp_dir("champr_apply_report");
+        let file = dir.join("report.json");
+        report.export(&file).unwrap();
+
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.contains("\"champName\": \"Zed\""));
+        assert_eq!(
+            serde_json::from_str::<ApplyReport>(&content).unwrap(),
+            report
+        );
+    }
+}
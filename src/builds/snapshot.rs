@@ -0,0 +1,466 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Write},
+    path::{Component, Path},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tar::{Archive, Builder, Header};
+
+use super::{
+    accept_package, fetch_builds, parse_source_spec, patch, progress::ProgressSender, send_planned,
+    ApplyOptions, FetchedBuilds,
+};
+use crate::{lcu, web};
+
+pub const SNAPSHOT_FORMAT: u32 = 1;
+
+const META_FILE: &str = "snapshot.json";
+const CHAMP_LIST_FILE: &str = "champion.json";
+const SOURCES_DIR: &str = "sources";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSource {
+    pub source: String,
+    pub version: String,
+    pub patch: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SnapshotMeta {
+    pub format: u32,
+    /// Seconds since the unix epoch.
+    pub created_at: u64,
+    pub source_list: Vec<web::Source>,
+    pub sources: Vec<SnapshotSource>,
+}
+
+impl SnapshotMeta {
+    /// Source list entries of the sources in the snapshot, what the list
+    /// looked like when it was exported. Sources missing from it are
+    /// labelled by name.
+    pub fn source_list(&self) -> Vec<web::Source> {
+        self.sources
+            .iter()
+            .map(|s| {
+                self.source_list
+                    .iter()
+                    .find(|item| item.value == s.source)
+                    .cloned()
+                    .unwrap_or_else(|| web::Source {
+                        label: s.source.clone(),
+                        value: s.source.clone(),
+                        is_aram: None,
+                        is_urf: None,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Everything an apply downloads, so it can run again without network. On
+/// disk it is a `.tar.gz` holding `snapshot.json`, DDragon's `champion.json`
+/// and `sources/{source}/{champ_name}.json` per champion.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub meta: SnapshotMeta,
+    pub champ_list: web::ChampListResp,
+    pub packages: BTreeMap<String, web::SourcePackage>,
+}
+
+fn append_json<W: Write, T: Serialize>(
+    builder: &mut Builder<W>,
+    path: &str,
+    value: &T,
+) -> Result<()> {
+    let buf = serde_json::to_vec(value)?;
+    let mut header = Header::new_gnu();
+    header.set_size(buf.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, &buf[..])?;
+    Ok(())
+}
+
+fn check_format(meta: &SnapshotMeta) -> Result<()> {
+    if meta.format > SNAPSHOT_FORMAT {
+        return Err(anyhow!(
+            "snapshot format {} is newer than this version supports",
+            meta.format
+        ));
+    }
+    Ok(())
+}
+
+fn read_json<R: Read, T: DeserializeOwned>(entry: &mut R) -> Result<T> {
+    let mut buf = vec![];
+    entry.read_to_end(&mut buf)?;
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+/// `sources/op.gg/Annie.json` becomes `("op.gg", "Annie")`.
+fn split_champ_path(path: &Path) -> Option<(String, String)> {
+    let parts: Vec<&str> = path
+        .components()
+        .map(|c| match c {
+            Component::Normal(s) => s.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<&str>>>()?;
+    match parts.as_slice() {
+        [SOURCES_DIR, source, file] => {
+            let champ_name = file.strip_suffix(".json")?;
+            Some((source.to_string(), champ_name.to_string()))
+        }
+        _ => None,
+    }
+}
+
+impl Snapshot {
+    /// Keeps the sources that were fetched, failed ones are left out.
+    fn from_fetched(fetched: FetchedBuilds, source_list: Vec<web::Source>) -> Result<Self> {
+        let mut packages = BTreeMap::new();
+        for (source, ret) in fetched.sources.into_iter() {
+            match ret {
+                Ok(package) => {
+                    packages.insert(source, package);
+                }
+                Err(e) => println!("[snapshot] leaving out {}, {:?}", source, e),
+            }
+        }
+        if packages.is_empty() {
+            return Err(anyhow!("no source could be fetched"));
+        }
+
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(Self {
+            meta: SnapshotMeta {
+                format: SNAPSHOT_FORMAT,
+                created_at,
+                source_list,
+                sources: vec![],
+            },
+            champ_list: fetched.champ_list,
+            packages,
+        })
+    }
+
+    pub fn write(&self, file: &Path) -> Result<()> {
+        let mut meta = self.meta.clone();
+        meta.format = SNAPSHOT_FORMAT;
+        meta.sources = self
+            .packages
+            .iter()
+            .map(|(source, package)| SnapshotSource {
+                source: source.clone(),
+                version: package.version.clone(),
+                patch: package.patch.clone(),
+            })
+            .collect();
+
+        let encoder = GzEncoder::new(File::create(file)?, Compression::default());
+        let mut builder = Builder::new(encoder);
+        append_json(&mut builder, META_FILE, &meta)?;
+        append_json(&mut builder, CHAMP_LIST_FILE, &self.champ_list)?;
+        for (source, package) in self.packages.iter() {
+            for (champ_name, data) in package.champs.iter() {
+                let path = format!("{}/{}/{}.json", SOURCES_DIR, source, champ_name);
+                append_json(&mut builder, &path, data)?;
+            }
+        }
+        builder.into_inner()?.finish()?;
+        Ok(())
+    }
+
+    pub fn read(file: &Path) -> Result<Self> {
+        let mut archive = Archive::new(GzDecoder::new(File::open(file)?));
+        let mut meta: Option<SnapshotMeta> = None;
+        let mut champ_list = None;
+        let mut champs: BTreeMap<String, web::SourcePackage> = BTreeMap::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            if path == Path::new(META_FILE) {
+                meta = Some(read_json(&mut entry)?);
+            } else if path == Path::new(CHAMP_LIST_FILE) {
+                champ_list = Some(read_json(&mut entry)?);
+            } else if let Some((source, champ_name)) = split_champ_path(&path) {
+                let data = read_json(&mut entry)?;
+                champs
+                    .entry(source)
+                    .or_default()
+                    .champs
+                    .insert(champ_name, data);
+            }
+        }
+
+        let meta = meta.ok_or_else(|| anyhow!("{} is not a snapshot", file.display()))?;
+        check_format(&meta)?;
+        let champ_list =
+            champ_list.ok_or_else(|| anyhow!("no champion list in {}", file.display()))?;
+
+        let mut packages = BTreeMap::new();
+        for s in meta.sources.iter() {
+            let mut package = champs.remove(&s.source).unwrap_or_default();
+            package.version = s.version.clone();
+            package.patch = s.patch.clone();
+            packages.insert(s.source.clone(), package);
+        }
+        Ok(Self {
+            meta,
+            champ_list,
+            packages,
+        })
+    }
+
+    /// Reads only `snapshot.json`, which `write` puts first, without
+    /// unpacking the builds.
+    pub fn read_meta(file: &Path) -> Result<SnapshotMeta> {
+        let mut archive = Archive::new(GzDecoder::new(File::open(file)?));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.path()? == Path::new(META_FILE) {
+                let meta = read_json(&mut entry)?;
+                check_format(&meta)?;
+                return Ok(meta);
+            }
+        }
+        Err(anyhow!("{} is not a snapshot", file.display()))
+    }
+
+    /// Picks the sources an apply asked for, as if they were just fetched.
+    /// No specs means every source in the snapshot. A pinned version must
+    /// match the one in the snapshot.
+    pub(super) fn into_fetched(
+        mut self,
+        specs: Vec<String>,
+        installed: Option<&str>,
+        options: &ApplyOptions,
+        progress: &ProgressSender,
+    ) -> FetchedBuilds {
+        let specs = if specs.is_empty() {
+            self.packages.keys().cloned().collect()
+        } else {
+            specs
+        };
+        let champ_names: Vec<String> = self.champ_list.data.keys().cloned().collect();
+        send_planned(&specs, &champ_names, options, progress);
+
+        let sources = specs
+            .iter()
+            .map(|spec| {
+                let (source, version) = parse_source_spec(spec);
+                let ret = match self.packages.remove(&source) {
+                    Some(package) if version != "latest" && version != package.version => {
+                        Err(anyhow!(
+                            "snapshot has {}@{}, not {}",
+                            source,
+                            package.version,
+                            version
+                        ))
+                    }
+                    Some(package) => accept_package(
+                        &source,
+                        package,
+                        installed,
+                        options.skip_other_patches,
+                        progress,
+                    ),
+                    None => Err(anyhow!("{} is not in the snapshot", source)),
+                };
+                (source, ret)
+            })
+            .collect();
+
+        FetchedBuilds {
+            champ_list: self.champ_list,
+            champ_names,
+            sources,
+        }
+    }
+}
+
+/// Downloads `sources` the way an apply would and saves them to `file`.
+/// `lol_dir` picks the champion list of the installed patch when given.
+pub async fn export_snapshot(
+    sources: Vec<String>,
+    lol_dir: String,
+    file: &Path,
+) -> Result<Snapshot> {
+    let auth = lcu::find_client(&lol_dir);
+    let installed = patch::installed_patch(&lol_dir, auth.as_ref()).await;
+    let fetched = fetch_builds(
+        sources,
+        installed,
+        &ApplyOptions::default(),
+        &ProgressSender::none(),
+    )
+    .await?;
+    let source_list = match crate::sources::fetch_source_list().await {
+        Ok(list) => list,
+        Err(e) => {
+            println!("[snapshot] no source list, {:?}", e);
+            vec![]
+        }
+    };
+
+    let snapshot = Snapshot::from_fetched(fetched, source_list)?;
+    snapshot.write(file)?;
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builds::{apply_builds, layout::Target, manifest::Manifest},
+        test_support::temp_dir,
+    };
+    use std::collections::HashMap;
+
+    /// A snapshot with one source holding one build for Annie.
+    fn make_snapshot() -> Snapshot {
+        let mut data = HashMap::new();
+        data.insert(
+            "Annie".to_string(),
+            web::ChampInfo {
+                id: "Annie".to_string(),
+                key: "1".to_string(),
+                name: "Annie".to_string(),
+                ..web::ChampInfo::default()
+            },
+        );
+        let mut champs = HashMap::new();
+        champs.insert(
+            "Annie".to_string(),
+            vec![web::ChampData {
+                item_builds: vec![web::ItemBuild {
+                    title: "Annie mid".to_string(),
+                    ..web::ItemBuild::default()
+                }],
+                ..web::ChampData::default()
+            }],
+        );
+        let mut packages = BTreeMap::new();
+        packages.insert(
+            "op.gg".to_string(),
+            web::SourcePackage {
+                version: "1.0.2".to_string(),
+                patch: "12.10.1".to_string(),
+                champs,
+            },
+        );
+
+        Snapshot {
+            meta: SnapshotMeta {
+                format: SNAPSHOT_FORMAT,
+                created_at: 1652860800,
+                source_list: vec![web::Source {
+                    label: "OP.GG".to_string(),
+                    value: "op.gg".to_string(),
+                    is_aram: None,
+                    is_urf: None,
+                }],
+                sources: vec![],
+            },
+            champ_list: web::ChampListResp {
+                version: "12.10.1".to_string(),
+                data,
+                ..web::ChampListResp::default()
+            },
+            packages,
+        }
+    }
+
+    #[test]
+    fn write_and_read() {
+        let dir = temp_dir("champr_snapshot_roundtrip");
+        let file = dir.join("builds.tar.gz");
+        let snapshot = make_snapshot();
+        snapshot.write(&file).unwrap();
+
+        let read = Snapshot::read(&file).unwrap();
+        assert_eq!(read.champ_list, snapshot.champ_list);
+        assert_eq!(read.packages, snapshot.packages);
+        assert_eq!(read.meta.sources[0].version, "1.0.2");
+
+        let meta = Snapshot::read_meta(&file).unwrap();
+        assert_eq!(meta, read.meta);
+        let list = meta.source_list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].label, "OP.GG");
+
+        assert_eq!(
+            split_champ_path(Path::new("sources/op.gg/Annie.json")),
+            Some(("op.gg".to_string(), "Annie".to_string()))
+        );
+        assert_eq!(split_champ_path(Path::new("sources/../Annie.json")), None);
+    }
+
+    #[test]
+    fn pick_sources() {
+        let options = ApplyOptions::default();
+        let progress = ProgressSender::none();
+
+        let fetched = make_snapshot().into_fetched(vec![], None, &options, &progress);
+        assert_eq!(fetched.sources.len(), 1);
+        assert!(fetched.sources[0].1.is_ok());
+
+        let specs = vec!["op.gg@1.0.1".to_string(), "lolalytics".to_string()];
+        let fetched = make_snapshot().into_fetched(specs, None, &options, &progress);
+        assert!(fetched.sources.iter().all(|(_, ret)| ret.is_err()));
+    }
+
+    #[tokio::test]
+    async fn apply_from_snapshot() {
+        let root = temp_dir("champr_snapshot_apply");
+        let file = root.join("builds.tar.gz");
+        make_snapshot().write(&file).unwrap();
+
+        let dir = root.join("items");
+        let options = ApplyOptions {
+            target: Target::Flat,
+            snapshot: Some(file.to_string_lossy().to_string()),
+            ..ApplyOptions::default()
+        };
+        let results = apply_builds(
+            vec!["op.gg".to_string()],
+            dir.to_string_lossy().to_string(),
+            options,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].ok);
+        assert!(dir.join("Annie").join("op.gg-Annie-0-0.json").exists());
+        assert_eq!(Manifest::load(&dir).sources[0].version, "1.0.2");
+
+        // every champion failing still reports them, and writes nothing
+        let options = ApplyOptions {
+            target: Target::Flat,
+            snapshot: Some(file.to_string_lossy().to_string()),
+            ..ApplyOptions::default()
+        };
+        let results = apply_builds(
+            vec!["lolalytics".to_string()],
+            dir.to_string_lossy().to_string(),
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].ok);
+        assert!(dir.join("Annie").join("op.gg-Annie-0-0.json").exists());
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    cancel::CancelToken,
+    manifest::{self, manifest_path, Manifest, MANIFEST_FILE},
+};
+
+const STAGING_SUFFIX: &str = ".champr-staging";
+const BACKUP_SUFFIX: &str = ".champr-backup";
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub fn staging_path(path: &Path) -> PathBuf {
+    with_suffix(path, STAGING_SUFFIX)
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, BACKUP_SUFFIX)
+}
+
+/// Relative paths of every file under `dir`.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = PathBuf::from(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            for rel in list_files(&entry.path())? {
+                files.push(name.join(rel));
+            }
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(files)
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)?;
+    Ok(())
+}
+
+/// Fills a new staging dir with copies of the files the manifest in `path`
+/// owns, and a manifest listing them. Files of the user are never copied.
+fn prepare(path: &Path, staging: &Path) -> Result<()> {
+    fs::create_dir_all(staging)?;
+    let mut manifest = Manifest::load(path);
+    manifest.release_edited(path);
+    for entry in manifest.entries.iter() {
+        let to = manifest::entry_path(staging, &entry.path)?;
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(manifest::entry_path(path, &entry.path)?, to)?;
+    }
+    manifest.save(staging)
+}
+
+/// Checks every file listed in the staged manifest can be read back.
+pub fn validate_files(staging: &Path) -> Result<()> {
+    for entry in Manifest::load(staging).entries.iter() {
+        let p = manifest::entry_path(staging, &entry.path)?;
+        let content = fs::read_to_string(&p)?;
+        serde_json::from_str::<serde_json::Value>(&content)
+            .map_err(|e| anyhow!("invalid build {}, {}", p.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Moves the files the old manifest owns to `backup` and the staged ones
+/// into `path`. The staged manifest goes last, once it is in place the
+/// apply has happened.
+fn move_files(path: &Path, staging: &Path, backup: &Path) -> Result<()> {
+    let mut old = Manifest::load(path);
+    old.release_edited(path);
+    for entry in old.entries.iter() {
+        move_file(
+            &manifest::entry_path(path, &entry.path)?,
+            &manifest::entry_path(backup, &entry.path)?,
+        )?;
+    }
+    if manifest_path(path).exists() {
+        move_file(&manifest_path(path), &manifest_path(backup))?;
+    }
+
+    let mut new = Manifest::load(staging);
+    let mut moved = vec![];
+    for entry in std::mem::take(&mut new.entries) {
+        let to = manifest::entry_path(path, &entry.path)?;
+        if to.exists() {
+            println!("keeping {}, it was not written by ChampR", to.display());
+            continue;
+        }
+        move_file(&manifest::entry_path(staging, &entry.path)?, &to)?;
+        moved.push(entry);
+    }
+    new.entries = moved;
+    new.save(staging)?;
+    move_file(&manifest_path(staging), &manifest_path(path))?;
+
+    for entry in old.entries.iter() {
+        manifest::remove_empty_parent(path, &manifest::entry_path(path, &entry.path)?)?;
+    }
+    Ok(())
+}
+
+/// Undoes a `move_files` that stopped halfway: drops the staged files that
+/// made it into `path` and puts the backup back, its manifest last. Once
+/// the staged manifest is in `path` so are all of its files, and the new
+/// tree is kept.
+fn roll_back(path: &Path, staging: &Path, backup: &Path) -> Result<()> {
+    if !manifest_path(staging).exists() {
+        if backup.exists() {
+            fs::remove_dir_all(backup)?;
+        }
+        return Ok(());
+    }
+
+    for entry in Manifest::load(staging).entries.iter() {
+        let moved = manifest::entry_path(path, &entry.path)?;
+        if moved.exists() && !manifest::entry_path(staging, &entry.path)?.exists() {
+            fs::remove_file(&moved)?;
+            manifest::remove_empty_parent(path, &moved)?;
+        }
+    }
+    if backup.exists() {
+        for rel in list_files(backup)? {
+            if rel != Path::new(MANIFEST_FILE) {
+                move_file(&backup.join(&rel), &path.join(&rel))?;
+            }
+        }
+        if manifest_path(backup).exists() {
+            move_file(&manifest_path(backup), &manifest_path(path))?;
+        }
+        fs::remove_dir_all(backup)?;
+    }
+    Ok(())
+}
+
+/// Cleans up after an apply that was killed halfway.
+pub fn recover(path: &Path) -> Result<()> {
+    let staging = staging_path(path);
+    let backup = backup_path(path);
+    if backup.exists() {
+        if manifest_path(&staging).exists() {
+            println!("rolling back unfinished apply: {}", path.display());
+            roll_back(path, &staging, &backup)?;
+        } else {
+            // the new manifest made it in, only the clean up was left
+            fs::remove_dir_all(&backup)?;
+        }
+    }
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    Ok(())
+}
+
+/// Moves the staged files into `path`. The files they replace are kept as
+/// a backup until all of them are in place.
+pub fn swap_in(path: &Path, staging: &Path) -> Result<()> {
+    let backup = backup_path(path);
+    if let Err(e) = move_files(path, staging, &backup) {
+        roll_back(path, staging, &backup)?;
+        return Err(e);
+    }
+
+    fs::remove_dir_all(staging)?;
+    if backup.exists() {
+        fs::remove_dir_all(&backup)?;
+    }
+    Ok(())
+}
+
+/// Runs `job` against a staging dir next to `path`, and only moves its
+/// files in once the job has finished and its output is valid. A failed or
+/// cancelled apply leaves `path` untouched. The staging dir starts with the
+/// files the manifest in `path` owns, it is up to `job` to clear out what it
+/// replaces and to list what it writes in the staged manifest. Anything
+/// else in `path` belongs to the user and is neither read nor moved.
+pub async fn with_staging<T, F, Fut>(path: &Path, cancel: &CancelToken, job: F) -> Result<T>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    recover(path)?;
+
+    let staging = staging_path(path);
+    prepare(path, &staging)?;
+
+    let ret = tokio::select! {
+        ret = job(staging.clone()) => ret,
+        _ = cancel.cancelled() => Err(anyhow!("apply cancelled")),
+    };
+    let ret = ret.and_then(|r| {
+        validate_files(&staging)?;
+        swap_in(path, &staging)?;
+        Ok(r)
+    });
+
+    if let Err(e) = &ret {
+        println!("apply failed, {} is untouched, {:?}", path.display(), e);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builds::manifest::ManifestEntry,
+        test_support::{temp_dir, TempDir},
+    };
+
+    fn entry(dir: &Path, rel: &str) -> ManifestEntry {
+        ManifestEntry {
+            source: "op.gg".to_string(),
+            champ_name: rel.split('/').next().unwrap().to_string(),
+            version: "1.0.0".to_string(),
+            path: rel.to_string(),
+            hash: manifest::hash_file(&dir.join(rel)).unwrap_or_default(),
+        }
+    }
+
+    /// `Annie/old.json` written by an earlier apply, next to a hand made
+    /// `Annie/mine.json` that isn't even valid JSON.
+    fn make_dir(name: &str) -> TempDir {
+        let dir = temp_dir(name);
+        fs::create_dir_all(dir.join("Annie")).unwrap();
+        fs::write(dir.join("Annie").join("old.json"), "{}").unwrap();
+        fs::write(dir.join("Annie").join("mine.json"), "my notes").unwrap();
+        let manifest = Manifest {
+            entries: vec![entry(&dir, "Annie/old.json")],
+            ..Manifest::default()
+        };
+        manifest.save(&dir).unwrap();
+        dir
+    }
+
+    fn assert_untouched(dir: &Path) {
+        assert_eq!(
+            fs::read_to_string(dir.join("Annie").join("old.json")).unwrap(),
+            "{}"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("Annie").join("mine.json")).unwrap(),
+            "my notes"
+        );
+        assert_eq!(
+            Manifest::load(dir).entries,
+            vec![entry(dir, "Annie/old.json")]
+        );
+        assert!(!dir.join("Zed").exists());
+        assert!(!staging_path(dir).exists());
+        assert!(!backup_path(dir).exists());
+    }
+
+    /// Writes `Zed/new.json` and lists it in the staged manifest.
+    async fn write_zed(staging: PathBuf, content: &str) -> Result<()> {
+        fs::create_dir_all(staging.join("Zed"))?;
+        fs::write(staging.join("Zed").join("new.json"), content)?;
+        let mut manifest = Manifest::load(&staging);
+        manifest.merge(vec![entry(&staging, "Zed/new.json")]);
+        manifest.save(&staging)
+    }
+
+    #[test]
+    fn stage_owned_files_only() {
+        let dir = make_dir("champr_staging_prepare");
+        let staging = staging_path(&dir);
+        prepare(&dir, &staging).unwrap();
+
+        assert!(staging.join("Annie").join("old.json").exists());
+        assert!(!staging.join("Annie").join("mine.json").exists());
+        assert_eq!(Manifest::load(&staging), Manifest::load(&dir));
+        validate_files(&staging).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failure_keeps_dir() {
+        let dir = make_dir("champr_staging_failure");
+        let ret: Result<()> = with_staging(&dir, &CancelToken::new(), |staging| async move {
+            write_zed(staging, "{}").await?;
+            Err(anyhow!("network down"))
+        })
+        .await;
+        assert!(ret.is_err());
+        assert_untouched(&dir);
+
+        // a truncated file fails validation
+        let ret = with_staging(&dir, &CancelToken::new(), |staging| {
+            write_zed(staging, "{\"title\":")
+        })
+        .await;
+        assert!(ret.is_err());
+        assert_untouched(&dir);
+    }
+
+    #[tokio::test]
+    async fn success_swaps_files() {
+        let dir = make_dir("champr_staging_success");
+        with_staging(&dir, &CancelToken::new(), |staging| async move {
+            let manifest = Manifest::load(&staging);
+            manifest.remove_files(&staging)?;
+            Manifest::default().save(&staging)?;
+            write_zed(staging, "{}").await
+        })
+        .await
+        .unwrap();
+
+        assert!(!dir.join("Annie").join("old.json").exists());
+        assert!(dir.join("Annie").join("mine.json").exists());
+        assert!(dir.join("Zed").join("new.json").exists());
+        assert_eq!(
+            Manifest::load(&dir).entries,
+            vec![entry(&dir, "Zed/new.json")]
+        );
+        assert!(!staging_path(&dir).exists());
+        assert!(!backup_path(&dir).exists());
+    }
+
+    #[tokio::test]
+    async fn recover_unfinished_swap() {
+        let dir = make_dir("champr_staging_recover");
+        let staging = staging_path(&dir);
+        prepare(&dir, &staging).unwrap();
+        fs::remove_dir_all(staging.join("Annie")).unwrap();
+        Manifest::default().save(&staging).unwrap();
+        write_zed(staging.clone(), "{}").await.unwrap();
+
+        // killed after moving the old file out and the new one in
+        let backup = backup_path(&dir);
+        move_file(
+            &dir.join("Annie").join("old.json"),
+            &backup.join("Annie").join("old.json"),
+        )
+        .unwrap();
+        move_file(&manifest_path(&dir), &manifest_path(&backup)).unwrap();
+        move_file(
+            &staging.join("Zed").join("new.json"),
+            &dir.join("Zed").join("new.json"),
+        )
+        .unwrap();
+
+        recover(&dir).unwrap();
+        assert_untouched(&dir);
+    }
+
+    #[tokio::test]
+    async fn roll_back_keeps_moved_manifest() {
+        let dir = make_dir("champr_staging_roll_back");
+        let staging = staging_path(&dir);
+        prepare(&dir, &staging).unwrap();
+        fs::remove_dir_all(staging.join("Annie")).unwrap();
+        Manifest::default().save(&staging).unwrap();
+        write_zed(staging.clone(), "{}").await.unwrap();
+
+        // every file and the manifest moved, the clean up after it failed
+        let backup = backup_path(&dir);
+        move_files(&dir, &staging, &backup).unwrap();
+        roll_back(&dir, &staging, &backup).unwrap();
+
+        assert!(dir.join("Zed").join("new.json").exists());
+        assert!(!dir.join("Annie").join("old.json").exists());
+        assert_eq!(
+            Manifest::load(&dir).entries,
+            vec![entry(&dir, "Zed/new.json")]
+        );
+        assert!(!backup.exists());
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::Path;
+
+use anyhow::Result;
+use clap::{ArgEnum, Parser, Subcommand};
+
+use crate::{
+    builds::{self, layout::Target, report, snapshot, ApplyOptions},
+    sources, web,
+};
+
+#[derive(Parser, Debug, PartialEq)]
+#[clap(
+    name = "champr",
+    about = "Apply item builds and runes for League of Legends"
+)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum Commands {
+    /// Apply builds to a folder without opening the window
+    Apply {
+        /// LoL folder to write item sets into, not needed for the lcu target
+        #[clap(
+            long,
+            required_unless_present = "target",
+            required_if_eq_any(&[("target", "game"), ("target", "flat")])
+        )]
+        dir: Option<String>,
+        /// Source to apply, can be repeated. `op.gg@1.0.1` pins a package
+        /// version, otherwise the latest one is used
+        #[clap(long = "source", required_unless_present = "snapshot")]
+        sources: Vec<String>,
+        /// Keep the builds already in the folder
+        #[clap(long)]
+        keep_old: bool,
+        /// Where item sets go: the game's Config folder, `<dir>/<Champ>/`
+        /// as is, or the running client's account item sets
+        #[clap(long, arg_enum, default_value = "game")]
+        target: TargetArg,
+        /// Leave out sources made for another patch than the installed game
+        #[clap(long)]
+        skip_other_patches: bool,
+        /// Take builds from a snapshot file instead of the network. Without
+        /// `--source` every source in it is applied
+        #[clap(long)]
+        snapshot: Option<String>,
+    },
+    /// Download sources into a snapshot file for applying offline
+    Snapshot {
+        /// Source to include, can be repeated, same format as for `apply`
+        #[clap(long = "source", required = true)]
+        sources: Vec<String>,
+        /// File to write, a `.tar.gz`
+        #[clap(long)]
+        out: String,
+        /// LoL folder, used to match the champion list to the installed patch
+        #[clap(long)]
+        dir: Option<String>,
+    },
+    /// List available sources
+    Sources {
+        /// List the sources in a snapshot file instead
+        #[clap(long)]
+        snapshot: Option<String>,
+    },
+    /// List published versions of a source, newest first
+    Versions { source: String },
+}
+
+/// `--target` values, see `builds::layout::Target`.
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum TargetArg {
+    Game,
+    Flat,
+    Lcu,
+}
+
+impl From<TargetArg> for Target {
+    fn from(arg: TargetArg) -> Self {
+        match arg {
+            TargetArg::Game => Target::Game,
+            TargetArg::Flat => Target::Flat,
+            TargetArg::Lcu => Target::Lcu,
+        }
+    }
+}
+
+async fn apply(dir: Option<String>, sources: Vec<String>, options: ApplyOptions) -> Result<bool> {
+    let dir = dir.unwrap_or_default();
+    println!("applying {:?} to {:?}", sources, options.target);
+    let results = builds::apply_builds(sources, dir, options).await?;
+
+    let mut all_done = true;
+    for s in report::summarize(&results).iter() {
+        println!("{}: {} done, {} failed", s.source, s.done, s.failed.len());
+        if !s.failed.is_empty() {
+            println!("  failed: {}", s.failed.join(", "));
+            all_done = false;
+        }
+    }
+    Ok(all_done)
+}
+
+async fn export_snapshot(sources: Vec<String>, out: String, dir: Option<String>) -> Result<bool> {
+    let dir = dir.unwrap_or_default();
+    let snapshot = snapshot::export_snapshot(sources.clone(), dir, Path::new(&out)).await?;
+    for s in snapshot.meta.sources.iter() {
+        let champs = snapshot
+            .packages
+            .get(&s.source)
+            .map(|p| p.champs.len())
+            .unwrap_or(0);
+        println!(
+            "{}@{}: {} champions, patch {}",
+            s.source, s.version, champs, s.patch
+        );
+    }
+    println!("saved to {}", out);
+    // sources that could not be fetched are left out of the snapshot
+    Ok(snapshot.packages.len() == sources.len())
+}
+
+async fn list_sources(snapshot: Option<String>) -> Result<bool> {
+    let list = match snapshot {
+        Some(file) => snapshot::Snapshot::read_meta(Path::new(&file))?.source_list(),
+        None => sources::fetch_source_list().await?,
+    };
+    for s in list.iter() {
+        let mut modes = vec![];
+        if s.is_aram == Some(true) {
+            modes.push("aram");
+        }
+        if s.is_urf == Some(true) {
+            modes.push("urf");
+        }
+        if !modes.is_empty() {
+            println!("{}\t{} ({})", s.value, s.label, modes.join(", "));
+        } else {
+            println!("{}\t{}", s.value, s.label);
+        }
+    }
+    Ok(true)
+}
+
+async fn list_versions(source: String) -> Result<bool> {
+    let npm_name = format!("@champ-r/{}", source);
+    for v in web::fetch_source_versions(npm_name).await?.iter() {
+        println!("{}\t{}\t{}", v.version, v.patch, v.published);
+    }
+    Ok(true)
+}
+
+/// Runs a subcommand and returns the process exit code.
+pub fn run(command: Commands) -> i32 {
+    let rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("failed to start runtime: {:?}", e);
+            return 1;
+        }
+    };
+
+    let ret = rt.block_on(async move {
+        match command {
+            Commands::Apply {
+                dir,
+                sources,
+                keep_old,
+                target,
+                skip_other_patches,
+                snapshot,
+            } => {
+                let options = ApplyOptions {
+                    keep_old,
+                    target: target.into(),
+                    skip_other_patches,
+                    snapshot,
+                    ..ApplyOptions::default()
+                };
+                apply(dir, sources, options).await
+            }
+            Commands::Snapshot { sources, out, dir } => export_snapshot(sources, out, dir).await,
+            Commands::Sources { snapshot } => list_sources(snapshot).await,
+            Commands::Versions { source } => list_versions(source).await,
+        }
+    });
+
+    match ret {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("error: {:?}", e);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args() {
+        let cli = Cli::try_parse_from([
+            "champr",
+            "apply",
+            "--dir",
+            "/games/lol",
+            "--source",
+            "op.gg",
+            "--source",
+            "lolalytics@1.0.1",
+            "--keep-old",
+            "--target",
+            "flat",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Commands::Apply {
+                dir: Some("/games/lol".to_string()),
+                sources: vec!["op.gg".to_string(), "lolalytics@1.0.1".to_string()],
+                keep_old: true,
+                target: TargetArg::Flat,
+                skip_other_patches: false,
+                snapshot: None,
+            })
+        );
+
+        assert_eq!(Cli::try_parse_from(["champr"]).unwrap().command, None);
+        assert_eq!(
+            Cli::try_parse_from(["champr", "versions", "op.gg"])
+                .unwrap()
+                .command,
+            Some(Commands::Versions {
+                source: "op.gg".to_string()
+            })
+        );
+        assert!(Cli::try_parse_from(["champr", "apply", "--dir", "/games/lol"]).is_err());
+
+        // only the lcu target works without a folder
+        assert!(Cli::try_parse_from(["champr", "apply", "--source", "op.gg"]).is_err());
+        assert!(
+            Cli::try_parse_from(["champr", "apply", "--source", "op.gg", "--target", "flat"])
+                .is_err()
+        );
+        let cli = Cli::try_parse_from(["champr", "apply", "--source", "op.gg", "--target", "lcu"])
+            .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Commands::Apply {
+                dir: None,
+                sources: vec!["op.gg".to_string()],
+                keep_old: false,
+                target: TargetArg::Lcu,
+                skip_other_patches: false,
+                snapshot: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_snapshot_args() {
+        let cli = Cli::try_parse_from([
+            "champr",
+            "apply",
+            "--dir",
+            "/games/lol",
+            "--snapshot",
+            "builds.tar.gz",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Apply { sources, snapshot: Some(_), .. }) if sources.is_empty()
+        ));
+
+        let cli = Cli::try_parse_from([
+            "champr",
+            "snapshot",
+            "--source",
+            "op.gg",
+            "--out",
+            "builds.tar.gz",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Commands::Snapshot {
+                sources: vec!["op.gg".to_string()],
+                out: "builds.tar.gz".to_string(),
+                dir: None,
+            })
+        );
+    }
+}
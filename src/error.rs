@@ -0,0 +1,166 @@
+use std::{fmt, io};
+
+/// What went wrong, in terms the UI can explain. Raised where the cause is
+/// known and recovered from any `anyhow::Error` chain with `classify`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Network { url: String, message: String },
+    HttpStatus { url: String, status: u16 },
+    Decode(String),
+    Fs(String),
+    Permission(String),
+    LcuUnreachable(String),
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network { url, message } if url.is_empty() => {
+                write!(f, "network error, {}", message)
+            }
+            Error::Network { url, message } => write!(f, "network error on {}, {}", url, message),
+            Error::HttpStatus { url, status } => write!(f, "{} answered {}", url, status),
+            Error::Decode(message) => write!(f, "unexpected data, {}", message),
+            Error::Fs(message) => write!(f, "file error, {}", message),
+            Error::Permission(message) => write!(f, "permission denied, {}", message),
+            Error::LcuUnreachable(message) => write!(f, "league client unreachable, {}", message),
+            Error::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn from_reqwest(e: &reqwest::Error) -> Error {
+    let url = e.url().map(|u| u.to_string()).unwrap_or_default();
+    if let Some(status) = e.status() {
+        return Error::HttpStatus {
+            url,
+            status: status.as_u16(),
+        };
+    }
+    if e.is_decode() {
+        return Error::Decode(e.to_string());
+    }
+    Error::Network {
+        url,
+        message: e.to_string(),
+    }
+}
+
+fn from_io(e: &io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => Error::Permission(e.to_string()),
+        _ => Error::Fs(e.to_string()),
+    }
+}
+
+/// The first cause in the chain of `e` that has a known kind. Context added
+/// on the way up is kept in the message of `Other`.
+pub fn classify(e: &anyhow::Error) -> Error {
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<Error>() {
+            return e.clone();
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return from_reqwest(e);
+        }
+        if let Some(e) = cause.downcast_ref::<serde_json::Error>() {
+            return Error::Decode(e.to_string());
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            return from_io(e);
+        }
+    }
+    Error::Other(format!("{:#}", e))
+}
+
+/// A failure as shown in the error panel: what the app was doing, the kind
+/// of error and the full chain for bug reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+    pub context: String,
+    pub error: Error,
+    pub details: String,
+}
+
+impl ErrorReport {
+    pub fn new(context: &str, e: &anyhow::Error) -> Self {
+        Self {
+            context: context.to_string(),
+            error: classify(e),
+            details: format!("{:?}", e),
+        }
+    }
+
+    /// Plain text meant to be pasted into an issue.
+    pub fn bug_report(&self) -> String {
+        format!(
+            "ChampR.rs {} on {}\n{}: {}\n\n{}",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            self.context,
+            self.error,
+            self.details
+        )
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn classify_chain() {
+        let e = anyhow::Error::new(Error::HttpStatus {
+            url: "Npm:/op.gg/Annie.json".to_string(),
+            status: 404,
+        })
+        .context("fetch champ detail");
+        assert_eq!(
+            classify(&e),
+            Error::HttpStatus {
+                url: "Npm:/op.gg/Annie.json".to_string(),
+                status: 404
+            }
+        );
+
+        let e = Err::<(), _>(io::Error::new(io::ErrorKind::PermissionDenied, "Config"))
+            .context("save build")
+            .unwrap_err();
+        assert_eq!(classify(&e), Error::Permission("Config".to_string()));
+
+        let e = anyhow::Error::new(io::Error::new(io::ErrorKind::NotFound, "Annie.json"));
+        assert_eq!(classify(&e), Error::Fs("Annie.json".to_string()));
+
+        let e = anyhow::Error::new(serde_json::from_str::<Vec<u32>>("{").unwrap_err());
+        assert!(matches!(classify(&e), Error::Decode(_)));
+
+        assert_eq!(
+            classify(&anyhow!("no source selected")),
+            Error::Other("no source selected".to_string())
+        );
+    }
+
+    #[test]
+    fn report_has_details() {
+        let e = anyhow::Error::new(Error::LcuUnreachable("league client not found".to_string()))
+            .context("push item sets");
+        let report = ErrorReport::new("apply builds", &e);
+        assert_eq!(
+            report.to_string(),
+            "apply builds: league client unreachable, league client not found"
+        );
+        let text = report.bug_report();
+        assert!(text.contains(env!("CARGO_PKG_VERSION")));
+        assert!(text.contains("push item sets"));
+    }
+}
@@ -1,73 +1,201 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use regex::Regex;
 use std::{
     env,
     fs::{self, File},
+    path::{Path, PathBuf},
     process::Command,
     sync::mpsc,
-    time::Duration,
 };
-use tokio::{task, time};
+use tokio::task;
+
+use crate::error::Error;
 // use serde::{Deserialize, Serialize};
 
+pub mod auto_apply;
+pub mod client;
+pub mod events;
+pub mod item_sets;
+pub mod perks;
+
+pub use client::LcuClient;
+
+#[derive(Default)]
 pub struct LCU {
-    auth_url: String,
+    auth: Option<LcuAuth>,
 }
 
 const APP_PORT_KEY: &str = "--app-port=";
 const AUTH_TOKEN_KEY: &str = "--remoting-auth-token=";
 const CONTROL_CHAR: &str = "\\";
+const CLIENT_UX_NAME: &str = "LeagueClientUx.exe";
+const LOCKFILE_NAME: &str = "lockfile";
+
+lazy_static! {
+    static ref PORT_REGEXP: Regex = Regex::new(r"--app-port=\d+").unwrap();
+    static ref TOKEN_REGEXP: Regex = Regex::new(r"--remoting-auth-token=[\w-]+").unwrap();
+}
 
 pub fn make_auth_url(token: String, port: String) -> String {
     format!("riot:{token}@127.0.0.1:{port}")
 }
 
-impl LCU {
-    pub fn new() -> Self {
-        Self {
-            auth_url: String::new(),
+/// Credentials of a running League client, as found in its lockfile or in
+/// the command line of `LeagueClientUx.exe`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LcuAuth {
+    pub pid: Option<u32>,
+    pub port: u16,
+    pub token: String,
+    pub protocol: String,
+}
+
+impl LcuAuth {
+    pub fn auth_url(&self) -> String {
+        make_auth_url(self.token.clone(), self.port.to_string())
+    }
+}
+
+/// Parses the content of a League `lockfile`, `name:pid:port:password:protocol`.
+pub fn parse_lockfile(content: &str) -> Result<LcuAuth> {
+    let parts: Vec<&str> = content.trim().split(':').collect();
+    if parts.len() != 5 {
+        return Err(anyhow!("invalid lockfile: {}", content));
+    }
+
+    Ok(LcuAuth {
+        pid: parts[1].parse().ok(),
+        port: parts[2].parse()?,
+        token: parts[3].to_string(),
+        protocol: parts[4].to_string(),
+    })
+}
+
+/// Looks for the lockfile in `lol_dir` and its parents, so picking the
+/// `Game` or `Config` folder still finds the client root.
+pub fn find_lockfile(lol_dir: &Path) -> Option<PathBuf> {
+    lol_dir
+        .ancestors()
+        .take(4)
+        .map(|dir| dir.join(LOCKFILE_NAME))
+        .find(|p| p.is_file())
+}
+
+pub fn read_lockfile(lol_dir: &Path) -> Result<LcuAuth> {
+    let p = find_lockfile(lol_dir)
+        .ok_or_else(|| anyhow!("lockfile not found in {}", lol_dir.display()))?;
+    let content = fs::read_to_string(&p)?;
+    parse_lockfile(&content)
+}
+
+/// Extracts the port and token from a `LeagueClientUx.exe` command line.
+pub fn parse_command_line(cmd_line: &str) -> Option<LcuAuth> {
+    let port = PORT_REGEXP
+        .find(cmd_line)?
+        .as_str()
+        .replace(APP_PORT_KEY, "")
+        .parse()
+        .ok()?;
+    let token = TOKEN_REGEXP
+        .find(cmd_line)?
+        .as_str()
+        .replace(AUTH_TOKEN_KEY, "")
+        .replace(CONTROL_CHAR, "");
+
+    Some(LcuAuth {
+        pid: None,
+        port,
+        token,
+        protocol: String::from("https"),
+    })
+}
+
+/// Scans `{proc_root}/*/cmdline` for `LeagueClientUx.exe`, which is how the
+/// client shows up under Wine/Lutris on Linux.
+pub fn scan_proc(proc_root: &Path) -> Option<LcuAuth> {
+    let entries = fs::read_dir(proc_root).ok()?;
+
+    for entry in entries.flatten() {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let cmd_line = match fs::read(entry.path().join("cmdline")) {
+            Ok(buf) => String::from_utf8_lossy(&buf).replace('\0', " "),
+            Err(_) => continue,
+        };
+        if !cmd_line.contains(CLIENT_UX_NAME) {
+            continue;
+        }
+
+        if let Some(auth) = parse_command_line(&cmd_line) {
+            return Some(LcuAuth {
+                pid: Some(pid),
+                ..auth
+            });
         }
     }
 
-    pub async fn start_parse_auth_task(&mut self) -> Result<()> {
-        let (tx, rx) = mpsc::channel();
-
-        let forever = task::spawn(async move {
-            let tx = tx.clone();
-            let mut interval = time::interval(Duration::from_secs(5));
-
-            loop {
-                interval.tick().await;
-
-                // match self.parse_auth().await {
-                //     Ok(s) => {
-                //         println!("auth url is {}", s);
-                //     }
-                //     Err(why) => {
-                //         println!("parse lcu auth failed, {:?}", why);
-                //     }
-                // };
-                tx.send(()).unwrap();
-            }
-        });
+    None
+}
 
-        forever.await?;
+#[cfg(target_os = "linux")]
+fn scan_processes() -> Option<LcuAuth> {
+    scan_proc(Path::new("/proc"))
+}
 
-        // drop(tx);
-        while let Ok(_) = rx.recv() {
-            println!("loop");
+#[cfg(not(target_os = "linux"))]
+fn scan_processes() -> Option<LcuAuth> {
+    None
+}
+
+/// Credentials found without asking for elevation: the lockfile under
+/// `lol_dir` first, then the process list.
+pub fn find_client(lol_dir: &str) -> Option<LcuAuth> {
+    if !lol_dir.is_empty() {
+        if let Ok(auth) = read_lockfile(Path::new(lol_dir)) {
+            return Some(auth);
         }
+    }
+    scan_processes()
+}
+
+/// Finds the credentials of the running client. With `elevate` it falls
+/// back to an elevated PowerShell on Windows, which prompts the user.
+pub async fn discover(lol_dir: String, elevate: bool) -> Result<LcuAuth> {
+    if let Some(auth) = find_client(&lol_dir) {
+        return Ok(auth);
+    }
 
-        Ok(())
+    if elevate && cfg!(target_os = "windows") {
+        return parse_auth().await;
     }
+
+    Err(Error::LcuUnreachable("league client not found".to_string()).into())
 }
 
-pub async fn parse_auth() -> Result<String> {
-    lazy_static! {
-        static ref PORT_REGEXP: Regex = Regex::new(r"--app-port=\d+").unwrap();
-        static ref TOKEN_REGEXP: Regex = Regex::new(r"--remoting-auth-token=\w+").unwrap();
+impl LCU {
+    pub fn new() -> Self {
+        Self { auth: None }
+    }
+
+    pub fn set_auth(&mut self, auth: LcuAuth) {
+        self.auth = Some(auth);
+    }
+
+    pub fn client(&self) -> Result<LcuClient> {
+        match &self.auth {
+            Some(auth) => LcuClient::new(auth),
+            None => Err(Error::LcuUnreachable("league client not connected".to_string()).into()),
+        }
     }
+}
 
+pub async fn parse_auth() -> Result<LcuAuth> {
     let (tx, rx) = mpsc::channel();
     let job = task::spawn(async move {
         let tx = tx.clone();
@@ -86,47 +214,97 @@ pub async fn parse_auth() -> Result<String> {
             display
         );
 
-        Command::new("powershell")
-            .args(["/C", &cmd_str])
-            .output()
-            .expect("failed to run powershell");
+        if let Err(why) = Command::new("powershell").args(["/C", &cmd_str]).output() {
+            println!("couldn't run powershell: {}", why);
+            return;
+        }
 
         let file_content = fs::read_to_string(&output_file_path).unwrap_or_default();
-        match PORT_REGEXP.is_match(&file_content) {
-            false => (),
-            true => {
-                let port_match = PORT_REGEXP.find(&file_content).unwrap();
-                let port = port_match.as_str().replace(APP_PORT_KEY, "");
-                let token_match = TOKEN_REGEXP.find(&file_content).unwrap();
-                let token = token_match
-                    .as_str()
-                    .replace(AUTH_TOKEN_KEY, "")
-                    .replace(CONTROL_CHAR, "");
-
-                let auth_url = make_auth_url(port, token);
-                tx.send(auth_url).unwrap();
-            }
+        if let Some(auth) = parse_command_line(&file_content) {
+            // nobody is waiting anymore if the receiver is gone
+            let _ = tx.send(auth);
         }
     });
     job.await?;
 
-    let auth_url = match rx.recv() {
-        Ok(url) => url,
-        Err(_) => String::from(""),
-    };
-    Ok(auth_url.clone())
+    rx.recv()
+        .map_err(|_| Error::LcuUnreachable("LeagueClientUx.exe is not running".to_string()).into())
 }
 
+#[cfg(test)]
 mod tests {
-    #![allow(unused_imports)]
-    use crate::lcu;
+    use crate::{
+        lcu::{self, LcuAuth},
+        test_support::temp_dir,
+    };
+    use std::fs;
 
+    /// Needs a running client and asks for elevation.
+    #[cfg(windows)]
+    #[ignore]
     #[tokio::test]
     async fn get_auth() {
-        // let mut client = LCU::new();
-        match lcu::parse_auth().await {
-            Ok(url) => print!("auth url: {}\n", url),
-            Err(why) => panic!("{:?}", why),
+        let auth = lcu::parse_auth().await.unwrap();
+        assert!(auth.port > 0);
+    }
+
+    #[test]
+    fn parse_lockfile() {
+        let auth = lcu::parse_lockfile("LeagueClient:1234:56789:s3cr3t-t0ken:https\n").unwrap();
+        assert_eq!(
+            auth,
+            LcuAuth {
+                pid: Some(1234),
+                port: 56789,
+                token: "s3cr3t-t0ken".to_string(),
+                protocol: "https".to_string(),
+            }
+        );
+        assert_eq!(auth.auth_url(), "riot:s3cr3t-t0ken@127.0.0.1:56789");
+        assert!(lcu::parse_lockfile("LeagueClient:1234").is_err());
+    }
+
+    #[test]
+    fn read_lockfile_from_sub_dir() {
+        let root = temp_dir("champr_lcu_lockfile");
+        let game_dir = root.join("Game").join("Config");
+        fs::create_dir_all(&game_dir).unwrap();
+        fs::write(root.join("lockfile"), "LeagueClient:1:2999:token:https").unwrap();
+
+        let auth = lcu::read_lockfile(&game_dir).unwrap();
+        assert_eq!(auth.port, 2999);
+        assert_eq!(auth.token, "token");
+
+        let empty = temp_dir("champr_lcu_no_lockfile");
+        assert!(lcu::read_lockfile(&empty).is_err());
+    }
+
+    #[test]
+    fn scan_fake_proc() {
+        let proc_root = temp_dir("champr_lcu_proc");
+        let write_cmdline = |pid: &str, args: &[&str]| {
+            let dir = proc_root.join(pid);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cmdline"), args.join("\0")).unwrap();
         };
+        write_cmdline("self", &["C:/LeagueClientUx.exe", "--app-port=1"]);
+        write_cmdline("12", &["/usr/bin/wine", "C:/Riot Games/LeagueClient.exe"]);
+        write_cmdline(
+            "42",
+            &[
+                "C:/Riot Games/League of Legends/LeagueClientUx.exe",
+                "--riotclient-auth-token=other",
+                "--remoting-auth-token=Ab_c-9",
+                "--app-port=51234",
+            ],
+        );
+
+        let auth = lcu::scan_proc(&proc_root).unwrap();
+        assert_eq!(auth.pid, Some(42));
+        assert_eq!(auth.port, 51234);
+        assert_eq!(auth.token, "Ab_c-9");
+
+        let empty = temp_dir("champr_lcu_empty_proc");
+        assert_eq!(lcu::scan_proc(&empty), None);
     }
 }
@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use super::{perks, LcuClient};
+use crate::{sources, web};
+
+const MY_SELECTION_ENDPOINT: &str = "/lol-champ-select/v1/session/my-selection";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpellSelection {
+    pub spell1_id: u64,
+    pub spell2_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoApplyResult {
+    pub source: String,
+    pub champ_name: String,
+    pub rune_page: Option<String>,
+    pub spells: Option<SpellSelection>,
+}
+
+/// The client and the sources name positions differently.
+pub fn normalize_position(position: &str) -> String {
+    match position.to_lowercase().as_str() {
+        "middle" | "mid" => "mid".to_string(),
+        "bottom" | "bot" | "adc" => "adc".to_string(),
+        "utility" | "support" | "sup" => "support".to_string(),
+        "jungle" | "jg" => "jungle".to_string(),
+        p => p.to_string(),
+    }
+}
+
+fn best_rune<'a>(runes: impl Iterator<Item = &'a web::Rune>) -> Option<&'a web::Rune> {
+    runes.max_by(|a, b| {
+        a.score
+            .partial_cmp(&b.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    })
+}
+
+/// The best rune for `position`, or the best rune overall when no rune
+/// targets that position.
+pub fn pick_rune(data: &[web::ChampData], position: &str) -> Option<web::Rune> {
+    let position = normalize_position(position);
+    let runes = || data.iter().flat_map(|d| d.runes.iter());
+
+    best_rune(runes().filter(|r| normalize_position(&r.position) == position))
+        .or_else(|| best_rune(runes()))
+        .cloned()
+}
+
+pub fn pick_spells(data: &[web::ChampData], position: &str) -> Option<SpellSelection> {
+    let position = normalize_position(position);
+    let champ = data
+        .iter()
+        .find(|d| normalize_position(&d.position) == position)
+        .or_else(|| data.first())?;
+
+    let ids: Vec<u64> = champ
+        .spells
+        .as_ref()?
+        .iter()
+        .filter_map(|id| id.parse().ok())
+        .collect();
+    match ids[..] {
+        [spell1_id, spell2_id, ..] => Some(SpellSelection {
+            spell1_id,
+            spell2_id,
+        }),
+        _ => None,
+    }
+}
+
+/// Fetches the champion from each source spec in priority order, stopping at
+/// the first source that has data for it. Returns the source name.
+pub async fn fetch_preferred(
+    sources: &[String],
+    champ_name: &str,
+) -> Result<(String, Vec<web::ChampData>)> {
+    for source in sources.iter() {
+        let provider = match sources::resolve(source) {
+            Ok(provider) => provider,
+            Err(e) => {
+                println!("[auto apply] {} skipped, {:#}", source, e);
+                continue;
+            }
+        };
+        match provider.champ_data(champ_name).await {
+            Ok(data) if !data.is_empty() => return Ok((provider.name().to_string(), data)),
+            Ok(_) => println!("[auto apply] no data for {} in {}", champ_name, source),
+            Err(e) => println!("[auto apply] {} skipped, {:#}", source, e),
+        }
+    }
+    Err(anyhow!("no source has data for {}", champ_name))
+}
+
+/// Pushes the rune and summoner spells picked from `data` into champ select.
+pub async fn apply_champ_data(
+    client: LcuClient,
+    source: String,
+    champ_name: String,
+    data: Vec<web::ChampData>,
+    position: String,
+) -> Result<AutoApplyResult> {
+    let mut result = AutoApplyResult {
+        source,
+        champ_name,
+        rune_page: None,
+        spells: None,
+    };
+
+    if let Some(rune) = pick_rune(&data, &position) {
+        let page = perks::apply_rune(client.clone(), rune).await?;
+        result.rune_page = Some(page.name);
+    }
+
+    if let Some(spells) = pick_spells(&data, &position) {
+        let _: () = client.patch(MY_SELECTION_ENDPOINT, &spells).await?;
+        result.spells = Some(spells);
+    }
+
+    Ok(result)
+}
+
+/// Runs when the local player locks `champion_id` in.
+pub async fn auto_apply(
+    client: LcuClient,
+    sources: Vec<String>,
+    champion_id: u64,
+    position: String,
+) -> Result<AutoApplyResult> {
+    let champ_list = web::fetch_latest_champ_list().await?;
+    let champ_name = web::find_champ_name(&champ_list, champion_id)
+        .ok_or_else(|| anyhow!("unknown champion id {}", champion_id))?;
+
+    let (source, data) = fetch_preferred(&sources, &champ_name).await?;
+    apply_champ_data(client, source, champ_name, data, position).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Response, StubServer};
+
+    fn rune(position: &str, score: f64, primary_style_id: u32) -> web::Rune {
+        web::Rune {
+            name: "Annie".to_string(),
+            position: position.to_string(),
+            score,
+            primary_style_id,
+            ..web::Rune::default()
+        }
+    }
+
+    fn champ_data() -> Vec<web::ChampData> {
+        vec![
+            web::ChampData {
+                position: "mid".to_string(),
+                spells: Some(vec!["4".to_string(), "14".to_string()]),
+                runes: vec![rune("mid", 1.0, 8100), rune("mid", 2.0, 8200)],
+                ..web::ChampData::default()
+            },
+            web::ChampData {
+                position: "support".to_string(),
+                spells: Some(vec!["4".to_string(), "3".to_string()]),
+                runes: vec![rune("support", 9.0, 8400)],
+                ..web::ChampData::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn pick_by_position() {
+        let data = champ_data();
+        assert_eq!(pick_rune(&data, "middle").unwrap().primary_style_id, 8200);
+        assert_eq!(pick_rune(&data, "utility").unwrap().primary_style_id, 8400);
+        assert_eq!(pick_rune(&data, "").unwrap().primary_style_id, 8400);
+        assert_eq!(pick_rune(&[], "middle"), None);
+
+        assert_eq!(
+            pick_spells(&data, "utility"),
+            Some(SpellSelection {
+                spell1_id: 4,
+                spell2_id: 3,
+            })
+        );
+        assert_eq!(
+            pick_spells(&data, "jungle"),
+            Some(SpellSelection {
+                spell1_id: 4,
+                spell2_id: 14,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_to_mock_client() {
+        let server = StubServer::https(|req| match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/lol-perks/v1/pages") => Response::json(200, "[]"),
+            ("GET", "/lol-perks/v1/inventory") => Response::json(200, r#"{"ownedPageCount":2}"#),
+            ("POST", "/lol-perks/v1/pages") => {
+                let page: serde_json::Value = req.json();
+                Response::json(
+                    200,
+                    &serde_json::json!({ "id": 1, "name": page["name"] }).to_string(),
+                )
+            }
+            ("PATCH", MY_SELECTION_ENDPOINT) => Response::status(204),
+            _ => Response::status(404),
+        })
+        .await;
+
+        let client =
+            LcuClient::with_root_cert(server.url(), "token".to_string(), server.cert_pem())
+                .unwrap();
+        let result = apply_champ_data(
+            client,
+            "op.gg".to_string(),
+            "Annie".to_string(),
+            champ_data(),
+            "middle".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.rune_page, Some("[ChampR] Annie mid".to_string()));
+
+        let requests = server.requests();
+        let page = requests.iter().find(|r| r.method == "POST").unwrap();
+        assert_eq!(page.json::<serde_json::Value>()["primaryStyleId"], 8200);
+        let selection = requests.iter().find(|r| r.method == "PATCH").unwrap();
+        assert_eq!(
+            selection.json::<serde_json::Value>(),
+            serde_json::json!({ "spell1Id": 4, "spell2Id": 14 })
+        );
+    }
+}
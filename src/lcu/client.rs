@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::{Certificate, Client, Method, RequestBuilder};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::LcuAuth;
+use crate::error::Error;
+
+/// Root of the certificate the League client serves, published by Riot as
+/// `riotgames.pem`.
+pub const RIOT_ROOT_CERT: &[u8] = include_bytes!("../../assets/riotgames.pem");
+
+/// HTTP client for the League client API, authenticated as `riot:<token>`.
+#[derive(Debug, Clone)]
+pub struct LcuClient {
+    base_url: String,
+    token: String,
+    client: Client,
+}
+
+impl LcuClient {
+    pub fn new(auth: &LcuAuth) -> Result<Self> {
+        Self::with_base_url(
+            format!("https://127.0.0.1:{}", auth.port),
+            auth.token.clone(),
+        )
+    }
+
+    pub fn with_base_url(base_url: String, token: String) -> Result<Self> {
+        Self::with_root_cert(base_url, token, RIOT_ROOT_CERT)
+    }
+
+    /// Only trusts `root_pem`, so the token never goes to whatever else
+    /// answers on the port. The client's certificate names `127.0.0.1`
+    /// in a way TLS libraries don't match against IPs, the pinned root is
+    /// what keeps others out.
+    pub fn with_root_cert(base_url: String, token: String, root_pem: &[u8]) -> Result<Self> {
+        let client = Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(Certificate::from_pem(root_pem)?)
+            .danger_accept_invalid_hostnames(true)
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+            client,
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    fn request(&self, method: Method, endpoint: &str) -> RequestBuilder {
+        let url = format!("{}{}", self.base_url, endpoint);
+        self.client
+            .request(method, url)
+            .basic_auth("riot", Some(&self.token))
+    }
+
+    async fn send<T: DeserializeOwned>(req: RequestBuilder) -> Result<T> {
+        let resp = req.send().await.map_err(|e| {
+            if e.is_connect() || e.is_timeout() {
+                anyhow::Error::new(Error::LcuUnreachable(e.to_string()))
+            } else {
+                e.into()
+            }
+        })?;
+        let status = resp.status();
+        let url = resp.url().to_string();
+        let buf = resp.bytes().await?;
+
+        if !status.is_success() {
+            println!(
+                "[lcu] {} {}, {}",
+                status,
+                url,
+                String::from_utf8_lossy(&buf)
+            );
+            return Err(Error::HttpStatus {
+                url,
+                status: status.as_u16(),
+            }
+            .into());
+        }
+
+        // `204 No Content` and friends are decoded as `null`, so callers
+        // can ask for `()` or `Option<T>`.
+        if buf.is_empty() {
+            return Ok(serde_json::from_str("null")?);
+        }
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
+        Self::send(self.request(Method::GET, endpoint)).await
+    }
+
+    pub async fn post<B, T>(&self, endpoint: &str, body: &B) -> Result<T>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        Self::send(self.request(Method::POST, endpoint).json(body)).await
+    }
+
+    pub async fn put<B, T>(&self, endpoint: &str, body: &B) -> Result<T>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        Self::send(self.request(Method::PUT, endpoint).json(body)).await
+    }
+
+    pub async fn patch<B, T>(&self, endpoint: &str, body: &B) -> Result<T>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        Self::send(self.request(Method::PATCH, endpoint).json(body)).await
+    }
+
+    pub async fn delete<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
+        Self::send(self.request(Method::DELETE, endpoint)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Response, StubServer};
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Summoner {
+        summoner_id: u64,
+        display_name: String,
+    }
+
+    #[tokio::test]
+    async fn typed_requests() {
+        let server = StubServer::https(|req| match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/lol-summoner/v1/current-summoner") => Response::json(
+                200,
+                r#"{"summonerId":42,"displayName":"Teemo","extra":true}"#,
+            ),
+            ("POST", "/echo") => Response::bytes(200, req.body.clone()),
+            ("DELETE", "/lol-perks/v1/pages/1") => Response::status(204),
+            _ => Response::json(404, r#"{"message":"not found"}"#),
+        })
+        .await;
+
+        let client =
+            LcuClient::with_root_cert(server.url(), "t0ken".to_string(), server.cert_pem())
+                .unwrap();
+
+        let summoner: Summoner = client
+            .get("/lol-summoner/v1/current-summoner")
+            .await
+            .unwrap();
+        assert_eq!(
+            summoner,
+            Summoner {
+                summoner_id: 42,
+                display_name: "Teemo".to_string(),
+            }
+        );
+
+        let echoed: Vec<u32> = client.post("/echo", &vec![1, 2, 3]).await.unwrap();
+        assert_eq!(echoed, vec![1, 2, 3]);
+
+        let _: () = client.delete("/lol-perks/v1/pages/1").await.unwrap();
+        let err = client.get::<Summoner>("/missing").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::HttpStatus {
+                url: format!("{}/missing", server.url()),
+                status: 404,
+            })
+        );
+
+        // "Basic " + base64("riot:t0ken")
+        let auth = server.requests()[0].headers.get("authorization").cloned();
+        assert_eq!(auth, Some("Basic cmlvdDp0MGtlbg==".to_string()));
+    }
+}
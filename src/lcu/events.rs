@@ -0,0 +1,332 @@
+use std::{collections::VecDeque, time::Duration};
+
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use iced::Subscription;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{
+        client::IntoClientRequest,
+        http::{header::AUTHORIZATION, HeaderValue},
+        Message as WsMessage,
+    },
+    Connector, MaybeTlsStream, WebSocketStream,
+};
+
+use super::{client::RIOT_ROOT_CERT, LcuAuth};
+
+pub const CHAMP_SELECT_EVENT: &str = "OnJsonApiEvent_lol-champ-select_v1_session";
+pub const GAMEFLOW_PHASE_EVENT: &str = "OnJsonApiEvent_lol-gameflow_v1_gameflow-phase";
+
+const CHAMP_SELECT_URI: &str = "/lol-champ-select/v1/session";
+const GAMEFLOW_PHASE_URI: &str = "/lol-gameflow/v1/gameflow-phase";
+
+// WAMP 1.0 message types used by the client.
+const WAMP_SUBSCRIBE: u8 = 5;
+const WAMP_EVENT: u8 = 8;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LcuEvent {
+    Connected,
+    Disconnected,
+    PhaseChanged(String),
+    PositionAssigned(String),
+    ChampionLocked { champion_id: u64, position: String },
+    ChampSelectEnded,
+}
+
+/// Payload of a WAMP event, `[8, topic, { data, eventType, uri }]`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiEvent {
+    #[serde(default)]
+    pub data: Value,
+    pub event_type: String,
+    pub uri: String,
+}
+
+pub fn parse_event(text: &str) -> Option<ApiEvent> {
+    let (kind, _topic, payload): (u8, String, ApiEvent) = serde_json::from_str(text).ok()?;
+    if kind != WAMP_EVENT {
+        return None;
+    }
+    Some(payload)
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChampSelectSession {
+    pub local_player_cell_id: i64,
+    pub my_team: Vec<TeamMember>,
+    pub actions: Vec<Vec<Action>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TeamMember {
+    pub cell_id: i64,
+    pub champion_id: u64,
+    pub assigned_position: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Action {
+    pub actor_cell_id: i64,
+    pub champion_id: u64,
+    pub completed: bool,
+    #[serde(rename = "type")]
+    pub type_field: String,
+}
+
+impl ChampSelectSession {
+    pub fn my_position(&self) -> String {
+        self.my_team
+            .iter()
+            .find(|m| m.cell_id == self.local_player_cell_id)
+            .map(|m| m.assigned_position.clone())
+            .unwrap_or_default()
+    }
+
+    /// The champion the local player has locked in, if any.
+    pub fn locked_champion(&self) -> Option<u64> {
+        self.actions
+            .iter()
+            .flatten()
+            .find(|a| {
+                a.actor_cell_id == self.local_player_cell_id
+                    && a.type_field == "pick"
+                    && a.completed
+                    && a.champion_id > 0
+            })
+            .map(|a| a.champion_id)
+    }
+}
+
+/// Turns raw session updates into events, emitting each change only once.
+#[derive(Default, Debug, Clone)]
+pub struct ChampSelectTracker {
+    phase: String,
+    position: String,
+    champion_id: Option<u64>,
+}
+
+impl ChampSelectTracker {
+    pub fn handle(&mut self, event: ApiEvent) -> Vec<LcuEvent> {
+        let mut events = vec![];
+
+        match event.uri.as_str() {
+            GAMEFLOW_PHASE_URI => {
+                let phase = event.data.as_str().unwrap_or_default().to_string();
+                if phase != self.phase {
+                    self.phase = phase.clone();
+                    events.push(LcuEvent::PhaseChanged(phase));
+                }
+            }
+            CHAMP_SELECT_URI if event.event_type == "Delete" => {
+                if self.champion_id.is_some() || !self.position.is_empty() {
+                    events.push(LcuEvent::ChampSelectEnded);
+                }
+                self.position = String::new();
+                self.champion_id = None;
+            }
+            CHAMP_SELECT_URI => {
+                let session: ChampSelectSession = match serde_json::from_value(event.data) {
+                    Ok(session) => session,
+                    Err(_) => return events,
+                };
+
+                let position = session.my_position();
+                if position != self.position {
+                    self.position = position.clone();
+                    if !position.is_empty() {
+                        events.push(LcuEvent::PositionAssigned(position));
+                    }
+                }
+
+                let champion_id = session.locked_champion();
+                if champion_id != self.champion_id {
+                    self.champion_id = champion_id;
+                    if let Some(champion_id) = champion_id {
+                        events.push(LcuEvent::ChampionLocked {
+                            champion_id,
+                            position: self.position.clone(),
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        events
+    }
+}
+
+async fn connect(auth: &LcuAuth) -> Result<WsStream> {
+    let mut req = format!("wss://127.0.0.1:{}", auth.port).into_client_request()?;
+    let credentials = base64::encode(format!("riot:{}", auth.token));
+    req.headers_mut().insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Basic {}", credentials))?,
+    );
+
+    // same trust as `LcuClient::with_root_cert`
+    let tls = native_tls::TlsConnector::builder()
+        .disable_built_in_roots(true)
+        .add_root_certificate(native_tls::Certificate::from_pem(RIOT_ROOT_CERT)?)
+        .danger_accept_invalid_hostnames(true)
+        .build()?;
+    let (mut ws, _) = tokio_tungstenite::connect_async_tls_with_config(
+        req,
+        None,
+        Some(Connector::NativeTls(tls)),
+    )
+    .await?;
+
+    for topic in [CHAMP_SELECT_EVENT, GAMEFLOW_PHASE_EVENT] {
+        let msg = serde_json::json!([WAMP_SUBSCRIBE, topic]).to_string();
+        ws.send(WsMessage::Text(msg)).await?;
+    }
+    Ok(ws)
+}
+
+enum State {
+    Disconnected(LcuAuth),
+    Connected {
+        auth: LcuAuth,
+        // boxed, the stream is far larger than the disconnected state
+        ws: Box<WsStream>,
+        tracker: ChampSelectTracker,
+        pending: VecDeque<LcuEvent>,
+    },
+}
+
+#[derive(Hash)]
+struct EventsId(u16, String);
+
+/// Streams champ select and gameflow events of the client behind `auth`,
+/// reconnecting every few seconds while the client is unreachable.
+pub fn subscription(auth: LcuAuth) -> Subscription<LcuEvent> {
+    let id = EventsId(auth.port, auth.token.clone());
+
+    iced_native::subscription::unfold(id, State::Disconnected(auth), |state| async move {
+        match state {
+            State::Disconnected(auth) => match connect(&auth).await {
+                Ok(ws) => (
+                    Some(LcuEvent::Connected),
+                    State::Connected {
+                        auth,
+                        ws: Box::new(ws),
+                        tracker: ChampSelectTracker::default(),
+                        pending: VecDeque::new(),
+                    },
+                ),
+                Err(e) => {
+                    println!("[lcu events] connect failed, {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    (None, State::Disconnected(auth))
+                }
+            },
+            State::Connected {
+                auth,
+                mut ws,
+                mut tracker,
+                mut pending,
+            } => loop {
+                if let Some(event) = pending.pop_front() {
+                    break (
+                        Some(event),
+                        State::Connected {
+                            auth,
+                            ws,
+                            tracker,
+                            pending,
+                        },
+                    );
+                }
+
+                match ws.next().await {
+                    Some(Ok(WsMessage::Text(text))) => {
+                        if let Some(event) = parse_event(&text) {
+                            pending.extend(tracker.handle(event));
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    _ => break (Some(LcuEvent::Disconnected), State::Disconnected(auth)),
+                }
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_event(event_type: &str, data: Value) -> String {
+        serde_json::json!([
+            8,
+            CHAMP_SELECT_EVENT,
+            { "data": data, "eventType": event_type, "uri": CHAMP_SELECT_URI }
+        ])
+        .to_string()
+    }
+
+    fn session(champion_id: u64, completed: bool) -> Value {
+        serde_json::json!({
+            "localPlayerCellId": 2,
+            "myTeam": [
+                { "cellId": 1, "championId": 103, "assignedPosition": "top" },
+                { "cellId": 2, "championId": champion_id, "assignedPosition": "middle" }
+            ],
+            "actions": [[
+                { "actorCellId": 1, "championId": 103, "completed": true, "type": "pick" },
+                { "actorCellId": 2, "championId": champion_id, "completed": completed, "type": "pick" }
+            ]]
+        })
+    }
+
+    #[test]
+    fn parse_wamp_event() {
+        let text = serde_json::json!([
+            8,
+            GAMEFLOW_PHASE_EVENT,
+            { "data": "ChampSelect", "eventType": "Update", "uri": GAMEFLOW_PHASE_URI }
+        ])
+        .to_string();
+        let event = parse_event(&text).unwrap();
+        assert_eq!(event.uri, GAMEFLOW_PHASE_URI);
+        assert_eq!(event.data, Value::from("ChampSelect"));
+
+        assert_eq!(parse_event("[0, \"session\", 1, \"RiotGames\"]"), None);
+        assert_eq!(parse_event("not json"), None);
+    }
+
+    #[test]
+    fn track_champ_select() {
+        let mut tracker = ChampSelectTracker::default();
+        let mut handle = |text: String| tracker.handle(parse_event(&text).unwrap());
+
+        assert_eq!(
+            handle(session_event("Update", session(1, false))),
+            vec![LcuEvent::PositionAssigned("middle".to_string())]
+        );
+        assert_eq!(handle(session_event("Update", session(1, false))), vec![]);
+        assert_eq!(
+            handle(session_event("Update", session(1, true))),
+            vec![LcuEvent::ChampionLocked {
+                champion_id: 1,
+                position: "middle".to_string(),
+            }]
+        );
+        assert_eq!(handle(session_event("Update", session(1, true))), vec![]);
+        assert_eq!(
+            handle(session_event("Delete", Value::Null)),
+            vec![LcuEvent::ChampSelectEnded]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::LcuClient;
+use crate::web;
+
+/// Every item set pushed by ChampR has a uid starting with this, sets
+/// without it belong to the user and are sent back untouched.
+pub const UID_PREFIX: &str = "champr-";
+
+const SUMMONER_ENDPOINT: &str = "/lol-summoner/v1/current-summoner";
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Summoner {
+    pub summoner_id: u64,
+    pub account_id: u64,
+}
+
+/// Body of `/lol-item-sets/v1/item-sets/{summonerId}/sets`. Sets are kept as
+/// raw JSON so fields of the user's own sets survive a round trip.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ItemSets {
+    pub account_id: u64,
+    pub item_sets: Vec<Value>,
+    pub timestamp: u64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemSetBlock {
+    pub hide_if_summoner_spell: String,
+    pub show_if_summoner_spell: String,
+    pub items: Vec<web::Item>,
+    #[serde(rename = "type")]
+    pub type_field: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemSet {
+    pub uid: String,
+    pub title: String,
+    pub associated_champions: Vec<u32>,
+    pub associated_maps: Vec<u32>,
+    pub blocks: Vec<ItemSetBlock>,
+    pub map: String,
+    pub mode: String,
+    pub preferred_item_slots: Vec<Value>,
+    pub sortrank: u32,
+    pub started_from: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+}
+
+pub fn sets_endpoint(summoner_id: u64) -> String {
+    format!("/lol-item-sets/v1/item-sets/{}/sets", summoner_id)
+}
+
+pub fn make_uid(source: &str, champ_name: &str, idx: usize, iidx: usize) -> String {
+    format!("{}{}-{}-{}-{}", UID_PREFIX, source, champ_name, idx, iidx)
+}
+
+pub fn is_champr_set(set: &Value) -> bool {
+    set["uid"]
+        .as_str()
+        .map(|uid| uid.starts_with(UID_PREFIX))
+        .unwrap_or(false)
+}
+
+/// Converts a build from a source into the client's item set schema.
+pub fn to_item_set(uid: String, champion_id: u32, build: &web::ItemBuild) -> ItemSet {
+    let associated_champions = if build.associated_champions.is_empty() {
+        vec![champion_id]
+    } else {
+        build.associated_champions.clone()
+    };
+    let blocks = build
+        .blocks
+        .iter()
+        .map(|b| ItemSetBlock {
+            items: b.items.clone().unwrap_or_default(),
+            type_field: b.type_field.clone(),
+            ..ItemSetBlock::default()
+        })
+        .collect();
+
+    ItemSet {
+        uid,
+        title: build.title.clone(),
+        associated_champions,
+        associated_maps: build.associated_maps.clone(),
+        blocks,
+        map: build.map.clone(),
+        mode: build.mode.clone(),
+        preferred_item_slots: vec![],
+        sortrank: build.sortrank,
+        started_from: build.started_from.clone(),
+        type_field: build.type_field.clone(),
+    }
+}
+
+/// Puts `sets` after the user's own sets. Without `keep_old` every earlier
+/// ChampR set is dropped, otherwise only the ones `sets` replace.
+pub fn merge(existing: Vec<Value>, sets: &[ItemSet], keep_old: bool) -> Result<Vec<Value>> {
+    let mut merged: Vec<Value> = existing
+        .into_iter()
+        .filter(|s| {
+            if !is_champr_set(s) {
+                return true;
+            }
+            keep_old
+                && !sets
+                    .iter()
+                    .any(|n| s["uid"].as_str() == Some(n.uid.as_str()))
+        })
+        .collect();
+    for s in sets.iter() {
+        merged.push(serde_json::to_value(s)?);
+    }
+    Ok(merged)
+}
+
+/// Replaces the ChampR item sets of the logged in account with `sets`.
+pub async fn push_item_sets(client: &LcuClient, sets: &[ItemSet], keep_old: bool) -> Result<()> {
+    let summoner: Summoner = client.get(SUMMONER_ENDPOINT).await?;
+    let endpoint = sets_endpoint(summoner.summoner_id);
+    let current: ItemSets = client.get(&endpoint).await?;
+
+    let body = ItemSets {
+        account_id: summoner.account_id,
+        item_sets: merge(current.item_sets, sets, keep_old)?,
+        timestamp: current.timestamp,
+    };
+    let _: () = client.put(&endpoint, &body).await?;
+    println!(
+        "[item-sets] pushed {} sets, {} in total",
+        sets.len(),
+        body.item_sets.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Response, StubServer};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn build(title: &str) -> web::ItemBuild {
+        web::ItemBuild {
+            title: title.to_string(),
+            associated_maps: vec![11],
+            blocks: vec![web::Block {
+                type_field: "Starters".to_string(),
+                items: Some(vec![web::Item {
+                    id: "1055".to_string(),
+                    count: 1,
+                }]),
+            }],
+            ..web::ItemBuild::default()
+        }
+    }
+
+    #[test]
+    fn convert_build() {
+        let set = to_item_set(make_uid("op.gg", "Annie", 0, 1), 1, &build("Annie mid"));
+        assert_eq!(set.uid, "champr-op.gg-Annie-0-1");
+        assert_eq!(set.associated_champions, vec![1]);
+        assert_eq!(set.blocks[0].items[0].id, "1055");
+
+        let value = serde_json::to_value(&set).unwrap();
+        assert_eq!(value["associatedMaps"], json!([11]));
+        assert_eq!(value["blocks"][0]["type"], json!("Starters"));
+        assert_eq!(value["blocks"][0]["hideIfSummonerSpell"], json!(""));
+    }
+
+    #[test]
+    fn merge_keeps_user_sets() {
+        let existing = vec![
+            json!({"uid": "b1c2", "title": "My Annie", "customField": 1}),
+            json!({"uid": "champr-op.gg-Annie-0-0", "title": "old"}),
+            json!({"uid": "champr-op.gg-Zed-0-0", "title": "old"}),
+        ];
+        let sets = vec![to_item_set(
+            make_uid("op.gg", "Annie", 0, 0),
+            1,
+            &build("new"),
+        )];
+
+        let merged = merge(existing.clone(), &sets, false).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0], existing[0]);
+        assert_eq!(merged[1]["title"], json!("new"));
+
+        let merged = merge(existing.clone(), &sets, true).unwrap();
+        let titles: Vec<&str> = merged
+            .iter()
+            .map(|s| s["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["My Annie", "old", "new"]);
+    }
+
+    #[tokio::test]
+    async fn push_sets() {
+        let stored = Arc::new(Mutex::new(json!({
+            "accountId": 7,
+            "itemSets": [{"uid": "user-set", "title": "Mine"}],
+            "timestamp": 100,
+        })));
+        let state = stored.clone();
+        let server = StubServer::https(move |req| {
+            let mut stored = state.lock().unwrap();
+            match (req.method.as_str(), req.path.as_str()) {
+                ("GET", "/lol-summoner/v1/current-summoner") => {
+                    Response::json(200, r#"{"summonerId":42,"accountId":7}"#)
+                }
+                ("GET", "/lol-item-sets/v1/item-sets/42/sets") => {
+                    Response::json(200, &stored.to_string())
+                }
+                ("PUT", "/lol-item-sets/v1/item-sets/42/sets") => {
+                    *stored = req.json();
+                    Response::status(201)
+                }
+                _ => Response::status(404),
+            }
+        })
+        .await;
+
+        let client =
+            LcuClient::with_root_cert(server.url(), "token".to_string(), server.cert_pem())
+                .unwrap();
+        let sets = vec![to_item_set(
+            make_uid("op.gg", "Annie", 0, 0),
+            1,
+            &build("Annie"),
+        )];
+        push_item_sets(&client, &sets, false).await.unwrap();
+
+        let stored = stored.lock().unwrap();
+        assert_eq!(stored["accountId"], json!(7));
+        assert_eq!(stored["itemSets"][0]["uid"], json!("user-set"));
+        assert_eq!(
+            stored["itemSets"][1]["uid"],
+            json!("champr-op.gg-Annie-0-0")
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::LcuClient;
+use crate::web;
+
+/// Every rune page created by ChampR starts with this, so it can find and
+/// recycle its own pages without touching the user's.
+pub const PAGE_PREFIX: &str = "[ChampR]";
+
+const PAGES_ENDPOINT: &str = "/lol-perks/v1/pages";
+const INVENTORY_ENDPOINT: &str = "/lol-perks/v1/inventory";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PerkPage {
+    pub id: u64,
+    pub name: String,
+    pub current: bool,
+    pub is_deletable: bool,
+    pub is_editable: bool,
+    pub last_modified: u64,
+    pub primary_style_id: u32,
+    pub sub_style_id: u32,
+    pub selected_perk_ids: Vec<u32>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PerkInventory {
+    pub owned_page_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewPerkPage {
+    pub name: String,
+    pub current: bool,
+    pub primary_style_id: u32,
+    pub sub_style_id: u32,
+    pub selected_perk_ids: Vec<u32>,
+}
+
+pub fn make_page_name(rune: &web::Rune) -> String {
+    let mut name = format!("{} {}", PAGE_PREFIX, rune.name);
+    if !rune.position.is_empty() {
+        name = format!("{} {}", name, rune.position);
+    }
+    name
+}
+
+pub fn is_champr_page(page: &PerkPage) -> bool {
+    page.is_deletable && page.name.starts_with(PAGE_PREFIX)
+}
+
+/// Picks the pages to delete before a new page can be created: a page with
+/// the same name is replaced, and when every slot is taken the oldest
+/// ChampR page makes room.
+pub fn pages_to_remove(
+    pages: &[PerkPage],
+    owned_page_count: usize,
+    name: &str,
+) -> Result<Vec<u64>> {
+    let mut ids: Vec<u64> = pages
+        .iter()
+        .filter(|p| is_champr_page(p) && p.name == name)
+        .map(|p| p.id)
+        .collect();
+
+    let used = pages.iter().filter(|p| p.is_deletable).count() - ids.len();
+    if used < owned_page_count {
+        return Ok(ids);
+    }
+
+    let oldest = pages
+        .iter()
+        .filter(|p| is_champr_page(p) && !ids.contains(&p.id))
+        .min_by_key(|p| (p.last_modified, p.id));
+    match oldest {
+        Some(page) => {
+            ids.push(page.id);
+            Ok(ids)
+        }
+        None => Err(anyhow!(
+            "no free rune page, remove one of your own pages first"
+        )),
+    }
+}
+
+/// Creates a rune page for `rune` and makes it the current page.
+pub async fn apply_rune(client: LcuClient, rune: web::Rune) -> Result<PerkPage> {
+    let pages: Vec<PerkPage> = client.get(PAGES_ENDPOINT).await?;
+    let inventory: PerkInventory = client.get(INVENTORY_ENDPOINT).await?;
+    let name = make_page_name(&rune);
+
+    for id in pages_to_remove(&pages, inventory.owned_page_count, &name)? {
+        let _: () = client.delete(&format!("{}/{}", PAGES_ENDPOINT, id)).await?;
+        println!("[perks] removed page {}", id);
+    }
+
+    let page = NewPerkPage {
+        name,
+        current: true,
+        primary_style_id: rune.primary_style_id,
+        sub_style_id: rune.sub_style_id,
+        selected_perk_ids: rune.selected_perk_ids.clone(),
+    };
+    let created: PerkPage = client.post(PAGES_ENDPOINT, &page).await?;
+    println!("[perks] created page {} {}", created.id, created.name);
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Response, StubServer};
+    use std::sync::{Arc, Mutex};
+
+    fn page(id: u64, name: &str, last_modified: u64) -> PerkPage {
+        PerkPage {
+            id,
+            name: name.to_string(),
+            is_deletable: true,
+            is_editable: true,
+            last_modified,
+            ..PerkPage::default()
+        }
+    }
+
+    #[test]
+    fn remove_oldest_champr_page_when_full() {
+        let pages = vec![
+            page(1, "My page", 1),
+            page(2, "[ChampR] Annie mid", 30),
+            page(3, "[ChampR] Zed mid", 20),
+        ];
+        assert_eq!(
+            pages_to_remove(&pages, 4, "[ChampR] Ahri mid").unwrap(),
+            Vec::<u64>::new()
+        );
+        assert_eq!(
+            pages_to_remove(&pages, 3, "[ChampR] Ahri mid").unwrap(),
+            vec![3]
+        );
+        assert_eq!(
+            pages_to_remove(&pages, 3, "[ChampR] Annie mid").unwrap(),
+            vec![2]
+        );
+        assert!(pages_to_remove(&pages[..1], 1, "[ChampR] Ahri mid").is_err());
+    }
+
+    #[tokio::test]
+    async fn apply_rune_replaces_page() {
+        let pages = Arc::new(Mutex::new(vec![
+            page(1, "My page", 1),
+            page(2, "[ChampR] Old mid", 2),
+        ]));
+        let state = pages.clone();
+        let server = StubServer::https(move |req| {
+            let mut pages = state.lock().unwrap();
+            match (req.method.as_str(), req.path.as_str()) {
+                ("GET", "/lol-perks/v1/pages") => {
+                    Response::json(200, &serde_json::to_string(&*pages).unwrap())
+                }
+                ("GET", "/lol-perks/v1/inventory") => {
+                    Response::json(200, r#"{"ownedPageCount":2}"#)
+                }
+                ("DELETE", path) => {
+                    let id: u64 = path.rsplit('/').next().unwrap().parse().unwrap();
+                    pages.retain(|p| p.id != id);
+                    Response::status(204)
+                }
+                ("POST", "/lol-perks/v1/pages") => {
+                    let new_page: serde_json::Value = req.json();
+                    let mut created = page(10, new_page["name"].as_str().unwrap(), 10);
+                    created.current = true;
+                    pages.push(created.clone());
+                    Response::json(200, &serde_json::to_string(&created).unwrap())
+                }
+                _ => Response::status(404),
+            }
+        })
+        .await;
+
+        let client =
+            LcuClient::with_root_cert(server.url(), "token".to_string(), server.cert_pem())
+                .unwrap();
+        let rune = web::Rune {
+            name: "Annie".to_string(),
+            position: "mid".to_string(),
+            primary_style_id: 8100,
+            sub_style_id: 8300,
+            selected_perk_ids: vec![8112, 8139],
+            ..web::Rune::default()
+        };
+        let created = apply_rune(client, rune).await.unwrap();
+        assert_eq!(created.name, "[ChampR] Annie mid");
+
+        let names: Vec<String> = pages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        assert_eq!(names, vec!["My page", "[ChampR] Annie mid"]);
+
+        let post = server
+            .requests()
+            .into_iter()
+            .find(|r| r.method == "POST")
+            .unwrap()
+            .json::<serde_json::Value>();
+        assert_eq!(post["primaryStyleId"], 8100);
+        assert_eq!(post["selectedPerkIds"], serde_json::json!([8112, 8139]));
+    }
+}
@@ -3,20 +3,52 @@ extern crate lazy_static;
 
 use iced::window::Mode;
 use iced::{
-    alignment, button, executor, image, scrollable, text_input, time, Alignment, Application,
-    Button, Checkbox, Color, Column, Command, Container, Element, Image, Length, Row, Scrollable,
-    Settings, Subscription, Text, TextInput,
+    alignment, button, executor, image, pick_list, scrollable, text_input, time, Alignment,
+    Application, Button, Checkbox, Color, Column, Command, Container, Element, Image, Length,
+    PickList, ProgressBar, Radio, Row, Scrollable, Settings, Subscription, Text, TextInput,
 };
+use std::collections::BTreeMap;
+
+use error::ErrorReport;
 
 pub mod builds;
+pub mod cli;
+pub mod error;
 pub mod fonts;
 pub mod images;
 pub mod lcu;
+pub mod runes;
+pub mod settings;
+pub mod sources;
 pub mod web;
 
+#[cfg(test)]
+mod test_support;
+
 fn main() -> Result<(), iced::Error> {
     tracing_subscriber::fmt::init();
 
+    let args = <cli::Cli as clap::Parser>::parse();
+    let saved = settings::load();
+    if let Err(e) = web::http::configure(&saved.http) {
+        println!("invalid network settings: {:?}", e);
+    }
+    if let Err(e) = web::mirror::set_mirrors(saved.mirrors.clone()) {
+        println!("invalid mirror settings: {:?}", e);
+    }
+    sources::set_custom_sources(saved.custom_sources.clone());
+    if let Some(command) = args.command {
+        let code = cli::run(command);
+        let mirrors = web::mirror::remember_fastest(&saved.mirrors);
+        if mirrors != saved.mirrors {
+            let current = settings::Settings { mirrors, ..saved };
+            if let Err(e) = settings::save(&current) {
+                println!("save settings failed: {:?}", e);
+            }
+        }
+        std::process::exit(code);
+    }
+
     let mut settings = Settings::default();
     settings.window.size = (320, 540);
     // settings.window.resizable = false;
@@ -28,8 +60,74 @@ fn main() -> Result<(), iced::Error> {
 struct SourceItem {
     value: String,
     label: String,
+    up_btn: button::State,
+    versions: Vec<web::SourceVersion>,
+    version_list: pick_list::State<web::SourceVersion>,
 }
 
+impl SourceItem {
+    fn new(label: String, value: String) -> Self {
+        Self {
+            label,
+            value,
+            up_btn: button::State::new(),
+            versions: vec![],
+            version_list: pick_list::State::default(),
+        }
+    }
+}
+
+/// Entry of the version list that follows the newest publish.
+fn latest_version() -> web::SourceVersion {
+    web::SourceVersion {
+        version: "latest".to_string(),
+        ..web::SourceVersion::default()
+    }
+}
+
+struct ChampItem {
+    name: String,
+    select_btn: button::State,
+}
+
+struct RuneItem {
+    source: String,
+    rune: web::Rune,
+    apply_btn: button::State,
+}
+
+impl RuneItem {
+    fn new(r: runes::SourceRune) -> Self {
+        Self {
+            source: r.source,
+            rune: r.rune,
+            apply_btn: button::State::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ApplyRun {
+    id: u64,
+    sources: Vec<String>,
+    path: String,
+    options: builds::ApplyOptions,
+    cancel: builds::cancel::CancelToken,
+}
+
+#[derive(Default)]
+struct RunePanel {
+    champs: Vec<ChampItem>,
+    champ_search_input: text_input::State,
+    champ_search: String,
+    champ_scrollable: scrollable::State,
+    selected_champ: String,
+    rune_scrollable: scrollable::State,
+    groups: Vec<(String, Vec<RuneItem>)>,
+}
+
+const MAX_ERRORS: usize = 20;
+
 #[derive(Default)]
 struct App {
     variants: Variant,
@@ -41,100 +139,174 @@ struct App {
     btn: button::State,
     lol_dir: String,
     keep_old: bool,
+    target: builds::layout::Target,
+    skip_other_patches: bool,
+    /// Source to package version, sources not listed follow `latest`.
+    pinned_versions: BTreeMap<String, String>,
+    /// Package version each source was last applied from.
+    applied_versions: BTreeMap<String, String>,
     dir_select_btn: button::State,
+    /// Snapshot file builds are applied from instead of the network.
+    snapshot: Option<String>,
+    snapshot_btn: button::State,
+    export_snapshot_btn: button::State,
     rune_ctrl_btn: button::State,
+    apply_run: Option<ApplyRun>,
+    apply_count: u64,
+    progress: builds::progress::ProgressState,
+    /// Results of the last apply, retries merged in.
+    last_report: Option<builds::report::ApplyReport>,
+    report_scrollable: scrollable::State,
+    retry_failed_btn: button::State,
+    export_report_btn: button::State,
+    dismiss_report_btn: button::State,
 
-    lcu_auth_url: String,
+    lcu_auth: Option<lcu::LcuAuth>,
+    lcu_phase: String,
+    auto_apply: bool,
     show_runes: bool,
+    rune_panel: RunePanel,
+
+    /// Failures shown in the status bar, newest last.
+    errors: Vec<ErrorReport>,
+    show_errors: bool,
+    lcu_error: Option<ErrorReport>,
+    error_btn: button::State,
+    copy_errors_btn: button::State,
+    clear_errors_btn: button::State,
+    error_scrollable: scrollable::State,
+
+    /// Last settings written to disk.
+    settings: settings::Settings,
 }
 
 impl App {
     pub fn new() -> Self {
         let mut items = vec![];
         for i in 1..3 {
-            let item = SourceItem {
-                label: format!("Source {}", i),
-                value: format!("source-{}", i),
-            };
+            let item = SourceItem::new(format!("Source {}", i), format!("source-{}", i));
             items.push(item);
         }
 
+        let settings = settings::load();
         Self {
             items,
+            lol_dir: settings.lol_dir.clone(),
+            selected: settings.selected.clone(),
+            keep_old: settings.keep_old,
+            target: settings.target,
+            skip_other_patches: settings.skip_other_patches,
+            pinned_versions: settings.pinned_versions.clone(),
+            applied_versions: settings.applied_versions.clone(),
+            show_runes: settings.show_runes,
+            auto_apply: settings.auto_apply,
+            settings,
             ..Self::default()
         }
     }
 
-    pub fn update_list(&mut self, items: Vec<SourceItem>) {
-        self.items = items;
+    fn current_settings(&self) -> settings::Settings {
+        settings::Settings {
+            lol_dir: self.lol_dir.clone(),
+            selected: self.selected.clone(),
+            keep_old: self.keep_old,
+            target: self.target,
+            skip_other_patches: self.skip_other_patches,
+            pinned_versions: self.pinned_versions.clone(),
+            applied_versions: self.applied_versions.clone(),
+            mirrors: web::mirror::remember_fastest(&self.settings.mirrors),
+            show_runes: self.show_runes,
+            auto_apply: self.auto_apply,
+            ..self.settings.clone()
+        }
     }
-}
 
-#[derive(Debug, Clone)]
-enum Message {
-    ToggleSource(bool, String),
-    OnInput(String),
-    OnClick,
-    OnFetchList(Vec<web::Source>),
-    OnReqFailed,
-    OnApplyBuildDone,
-    OnApplyBuildFailed,
-    ToggleKeepOld(bool),
-    OnSelectDir,
-    Tick,
-    OnGetLcuAuth(String),
-    OnToggleRunes,
-}
-
-fn result_handler(ret: anyhow::Result<Vec<web::Source>>) -> Message {
-    match ret {
-        Ok(list) => Message::OnFetchList(list),
-        Err(_err) => Message::OnReqFailed,
+    fn save_settings(&mut self) {
+        let current = self.current_settings();
+        if current == self.settings {
+            return;
+        }
+        match settings::save(&current) {
+            Ok(_) => self.settings = current,
+            Err(e) => println!("save settings failed: {:?}", e),
+        }
     }
-}
 
-fn apply_result_handler(ret: anyhow::Result<Vec<(bool, String, String)>>) -> Message {
-    match ret {
-        Ok(_) => Message::OnApplyBuildDone,
-        Err(_e) => Message::OnApplyBuildFailed,
+    pub fn update_list(&mut self, items: Vec<SourceItem>) {
+        self.items = items;
     }
-}
 
-fn lcu_auth_handler(ret: anyhow::Result<String>) -> Message {
-    match ret {
-        Ok(s) => Message::OnGetLcuAuth(s),
-        Err(_e) => Message::OnReqFailed,
+    /// Selected sources as specs, `op.gg@1.0.1` when a version is pinned.
+    fn selected_specs(&self) -> Vec<String> {
+        self.selected
+            .iter()
+            .map(|s| match self.pinned_versions.get(s) {
+                Some(version) => format!("{}@{}", s, version),
+                None => s.clone(),
+            })
+            .collect()
     }
-}
 
-impl Application for App {
-    type Executor = executor::Default;
-    type Message = Message;
-    type Flags = ();
+    fn start_apply(&mut self, sources: Vec<String>, path: String, options: builds::ApplyOptions) {
+        let options = builds::ApplyOptions {
+            lcu_auth: self.lcu_auth.clone(),
+            ..options
+        };
+        self.apply_count += 1;
+        self.progress = builds::progress::ProgressState::default();
+        self.apply_run = Some(ApplyRun {
+            id: self.apply_count,
+            sources,
+            path,
+            options,
+            cancel: builds::cancel::CancelToken::new(),
+        });
+    }
 
-    fn new(_flags: ()) -> (Self, Command<Message>) {
-        (
-            App::new(),
-            Command::perform(web::fetch_source_list(), result_handler),
-        )
+    /// Lists the sources of a snapshot in place of the published list, so
+    /// the snapshot can be applied offline.
+    fn open_snapshot(&mut self, file: String) -> anyhow::Result<()> {
+        let meta = builds::snapshot::Snapshot::read_meta(std::path::Path::new(&file))?;
+        self.items = meta
+            .source_list()
+            .into_iter()
+            .map(|s| SourceItem::new(s.label, s.value))
+            .collect();
+        for item in self.items.iter_mut() {
+            if let Some(s) = meta.sources.iter().find(|s| s.source == item.value) {
+                item.versions = vec![web::SourceVersion {
+                    version: s.version.clone(),
+                    patch: s.patch.clone(),
+                    ..web::SourceVersion::default()
+                }];
+            }
+        }
+        self.snapshot = Some(file);
+        Ok(())
     }
 
-    fn title(&self) -> String {
-        String::from("ChampR.rs")
+    fn report(&mut self, report: ErrorReport) {
+        self.errors.push(report);
+        if self.errors.len() > MAX_ERRORS {
+            self.errors.remove(0);
+        }
     }
 
-    fn update(&mut self, message: Self::Message) -> Command<Message> {
+    fn handle(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::ToggleSource(checked, s) => {
                 if checked {
-                    self.selected.push(s);
+                    self.selected.push(s.clone());
+                    let loaded = self.snapshot.is_some()
+                        || self
+                            .items
+                            .iter()
+                            .any(|i| i.value == s && !i.versions.is_empty());
+                    if !loaded {
+                        return fetch_versions(s);
+                    }
                 } else {
-                    let idx = self
-                        .selected
-                        .iter()
-                        .position(|i| i.to_string() == s)
-                        .unwrap();
-                    self.selected.remove(idx);
+                    self.selected.retain(|i| *i != s);
                 }
                 println!("{:?}", self.selected);
                 Command::none()
@@ -144,46 +316,249 @@ impl Application for App {
                 Command::none()
             }
             Message::OnClick => {
-                if self.selected.len() == 0 || self.lol_dir.chars().count() == 0 {
+                // the client is found without a folder when pushing item sets
+                let needs_dir = self.target != builds::layout::Target::Lcu;
+                if self.selected.is_empty()
+                    || (needs_dir && self.lol_dir.chars().count() == 0)
+                    || self.apply_run.is_some()
+                {
                     return Command::none();
                 }
 
-                let selected = self.selected.clone();
-                let lol_dir = self.lol_dir.to_owned();
-                Command::perform(
-                    builds::apply_builds(selected, lol_dir, self.keep_old),
-                    apply_result_handler,
-                )
+                let sources = match &self.snapshot {
+                    // a snapshot holds one version of each source
+                    Some(_) => self
+                        .selected
+                        .iter()
+                        .filter(|s| self.items.iter().any(|i| i.value == **s))
+                        .cloned()
+                        .collect(),
+                    None => self.selected_specs(),
+                };
+                if sources.is_empty() {
+                    return Command::none();
+                }
+                let options = builds::ApplyOptions {
+                    keep_old: self.keep_old,
+                    target: self.target,
+                    skip_other_patches: self.skip_other_patches,
+                    snapshot: self.snapshot.clone(),
+                    ..builds::ApplyOptions::default()
+                };
+                self.start_apply(sources, self.lol_dir.to_owned(), options);
+                self.last_report = None;
+                Command::none()
             }
+            Message::RetryFailed => {
+                let report = match &self.last_report {
+                    Some(report) if self.apply_run.is_none() => report,
+                    _ => return Command::none(),
+                };
+                let only_champs = report.failed_champs();
+                if only_champs.is_empty() {
+                    return Command::none();
+                }
+                // builds that made it the first time stay in place
+                let options = builds::ApplyOptions {
+                    keep_old: true,
+                    target: report.target,
+                    skip_other_patches: self.skip_other_patches,
+                    snapshot: self.snapshot.clone(),
+                    only_champs,
+                    ..builds::ApplyOptions::default()
+                };
+                let (sources, path) = (report.retry_sources(), report.path.clone());
+                self.start_apply(sources, path, options);
+                Command::none()
+            }
+            Message::ExportReport => {
+                let report = match &self.last_report {
+                    Some(report) => report.clone(),
+                    None => return Command::none(),
+                };
+                if let Some(file) =
+                    tinyfiledialogs::save_file_dialog("Export apply report", "champr-report.json")
+                {
+                    if let Err(e) = report.export(std::path::Path::new(&file)) {
+                        self.report(ErrorReport::new("export apply report", &e));
+                    }
+                }
+                Command::none()
+            }
+            Message::DismissReport => {
+                self.last_report = None;
+                Command::none()
+            }
+            Message::CancelApply => {
+                if let Some(run) = &self.apply_run {
+                    run.cancel.cancel();
+                }
+                Command::none()
+            }
+            Message::OnApplyEvent(event) => match event {
+                builds::progress::ApplyEvent::Progress(p) => {
+                    self.progress.update(&p);
+                    Command::none()
+                }
+                builds::progress::ApplyEvent::Finished(ret) => {
+                    let run = self.apply_run.take();
+                    if let (Ok(results), Some(run)) = (&ret, run) {
+                        // a source that wrote nothing keeps its old version
+                        for s in self.progress.sources.iter() {
+                            if s.done > 0 && !s.version.is_empty() {
+                                self.applied_versions
+                                    .insert(s.source.clone(), s.version.clone());
+                            }
+                        }
+                        let retry = !run.options.only_champs.is_empty();
+                        if let (true, Some(report)) = (retry, self.last_report.as_mut()) {
+                            report.merge_retry(results.clone());
+                        } else {
+                            self.last_report = Some(builds::report::ApplyReport::new(
+                                run.sources,
+                                run.path,
+                                run.options.target,
+                                results.clone(),
+                            ));
+                        }
+                    }
+                    self.handle(apply_result_handler(ret))
+                }
+            },
             Message::OnToggleRunes => {
                 self.show_runes = !self.show_runes;
+                if self.show_runes && self.rune_panel.champs.is_empty() {
+                    return Command::perform(web::fetch_latest_champ_list(), champ_list_handler);
+                }
+                Command::none()
+            }
+            Message::OnFetchChampList(names) => {
+                self.rune_panel.champs = names
+                    .into_iter()
+                    .map(|name| ChampItem {
+                        name,
+                        select_btn: button::State::new(),
+                    })
+                    .collect();
+                Command::none()
+            }
+            Message::OnChampSearch(s) => {
+                self.rune_panel.champ_search = s;
+                Command::none()
+            }
+            Message::SelectChamp(champ_name) => {
+                self.rune_panel.selected_champ = champ_name.clone();
+                self.rune_panel.groups = vec![];
+                Command::perform(
+                    runes::fetch_champ_runes(self.selected_specs(), champ_name.clone()),
+                    move |ret| match ret {
+                        Ok(list) => Message::OnFetchRunes(champ_name.clone(), list),
+                        Err(e) => Message::OnReqFailed(ErrorReport::new("fetch runes", &e)),
+                    },
+                )
+            }
+            Message::OnFetchRunes(champ_name, list) => {
+                // the user may have picked another champion in the meantime
+                if champ_name == self.rune_panel.selected_champ {
+                    self.rune_panel.groups = runes::group_by_position(list)
+                        .into_iter()
+                        .map(|(position, items)| {
+                            (position, items.into_iter().map(RuneItem::new).collect())
+                        })
+                        .collect();
+                }
                 Command::none()
             }
+            Message::ApplyRune(rune) => {
+                let client = match &self.lcu_auth {
+                    Some(auth) => lcu::LcuClient::new(auth),
+                    None => Err(error::Error::LcuUnreachable(
+                        "league client is not running".to_string(),
+                    )
+                    .into()),
+                };
+                match client {
+                    Ok(client) => {
+                        Command::perform(lcu::perks::apply_rune(client, rune), apply_rune_handler)
+                    }
+                    Err(e) => {
+                        self.report(ErrorReport::new("apply rune", &e));
+                        Command::none()
+                    }
+                }
+            }
+            Message::OnApplyRuneDone(name) => {
+                println!("applied rune page: {}", name);
+                Command::none()
+            }
+            Message::OnApplyRuneFailed(report) => {
+                self.report(report);
+                Command::none()
+            }
+            Message::OnFetchList(_) if self.snapshot.is_some() => Command::none(),
             Message::OnFetchList(list) => {
                 let mut items: Vec<SourceItem> = vec![];
                 for i in list {
-                    items.push(SourceItem {
-                        label: i.label,
-                        value: i.value,
-                    });
+                    items.push(SourceItem::new(i.label, i.value));
                 }
                 self.update_list(items);
+                // sources saved by an older list may be gone
+                let items = &self.items;
+                self.selected
+                    .retain(|s| items.iter().any(|i| i.value == *s));
+                Command::batch(self.selected.iter().map(|s| fetch_versions(s.clone())))
+            }
+            Message::OnReqFailed(report) => {
+                self.report(report);
+                Command::none()
+            }
+            Message::OnApplyBuildDone => {
+                println!(
+                    "apply done, {} ok, {} failed",
+                    self.progress.done, self.progress.failed
+                );
+                Command::none()
+            }
+            Message::OnApplyBuildFailed(report) => {
+                self.report(report);
+                Command::none()
+            }
+            Message::ToggleErrors => {
+                self.show_errors = !self.show_errors;
+                Command::none()
+            }
+            Message::CopyErrors => {
+                let text = self
+                    .errors
+                    .iter()
+                    .map(ErrorReport::bug_report)
+                    .collect::<Vec<String>>()
+                    .join("\n\n---\n\n");
+                iced::clipboard::write(text)
+            }
+            Message::ClearErrors => {
+                self.errors.clear();
+                self.show_errors = false;
                 Command::none()
             }
-            Message::OnReqFailed => Command::none(),
-            Message::OnApplyBuildDone => Command::none(),
-            Message::OnApplyBuildFailed => Command::none(),
             Message::ToggleKeepOld(checked) => {
                 self.keep_old = checked;
                 Command::none()
             }
+            Message::ToggleSkipOtherPatches(checked) => {
+                self.skip_other_patches = checked;
+                Command::none()
+            }
+            Message::SelectTarget(target) => {
+                self.target = target;
+                Command::none()
+            }
             Message::OnSelectDir => {
                 let mut folder: String = String::from("");
-                match tinyfiledialogs::select_folder_dialog("Select LoL folder", &self.lol_dir) {
-                    Some(result) => {
-                        folder = result;
-                    }
-                    _ => {}
+                if let Some(result) =
+                    tinyfiledialogs::select_folder_dialog("Select LoL folder", &self.lol_dir)
+                {
+                    folder = result;
                 }
                 println!("selected folder: {}", folder);
                 if folder.chars().count() > 0 {
@@ -191,34 +566,320 @@ impl Application for App {
                 }
                 Command::none()
             }
+            Message::OnSelectSnapshot => {
+                if self.snapshot.is_some() {
+                    self.snapshot = None;
+                    return Command::perform(sources::fetch_source_list(), result_handler);
+                }
+                let file = match tinyfiledialogs::open_file_dialog(
+                    "Open snapshot",
+                    "",
+                    Some((&["*.tar.gz"], "Snapshot")),
+                ) {
+                    Some(file) => file,
+                    None => return Command::none(),
+                };
+                if let Err(e) = self.open_snapshot(file) {
+                    self.report(ErrorReport::new("open snapshot", &e));
+                }
+                Command::none()
+            }
+            Message::ExportSnapshot => {
+                if self.selected.is_empty() || self.snapshot.is_some() {
+                    return Command::none();
+                }
+                let file = match tinyfiledialogs::save_file_dialog(
+                    "Export snapshot",
+                    "champr-snapshot.tar.gz",
+                ) {
+                    Some(file) => file,
+                    None => return Command::none(),
+                };
+                let (specs, dir) = (self.selected_specs(), self.lol_dir.clone());
+                Command::perform(
+                    async move {
+                        let path = std::path::Path::new(&file);
+                        builds::snapshot::export_snapshot(specs, dir, path).await?;
+                        Ok(file)
+                    },
+                    snapshot_export_handler,
+                )
+            }
+            Message::OnSnapshotExported(file) => {
+                println!("snapshot saved to {}", file);
+                Command::none()
+            }
             Message::Tick => {
-                println!("tick");
-                // let mut lcu = lcu::LCU::new();
-                #[cfg(target_os = "windows")]
-                return Command::perform(lcu::parse_auth(), lcu_auth_handler);
-
-                #[cfg(not(target_os = "windows"))]
-                return Command::none();
+                if self.lcu_auth.is_some() {
+                    return Command::none();
+                }
+                // polled, so never prompts for elevation
+                Command::perform(lcu::discover(self.lol_dir.clone(), false), lcu_auth_handler)
             }
             Message::OnGetLcuAuth(auth) => {
-                if self.lcu_auth_url != auth && auth.len() > 0 {
-                    println!("update lcu auth, {}", auth);
-                    self.lcu_auth_url = auth;
+                if self.lcu_auth.as_ref() != Some(&auth) {
+                    println!("update lcu auth, port {}", auth.port);
+                    self.lcu_auth = Some(auth);
+                }
+                self.lcu_error = None;
+                Command::none()
+            }
+            Message::OnLcuAuthFailed(report) => {
+                // polled every few seconds, so only kept for the status bar
+                self.lcu_error = Some(report);
+                Command::none()
+            }
+            Message::ToggleAutoApply(checked) => {
+                self.auto_apply = checked;
+                Command::none()
+            }
+            Message::OnFetchVersions(source, versions) => {
+                if let Some(item) = self.items.iter_mut().find(|i| i.value == source) {
+                    item.versions = versions;
+                }
+                Command::none()
+            }
+            Message::PinVersion(source, version) => {
+                if version.version == "latest" {
+                    self.pinned_versions.remove(&source);
+                } else {
+                    self.pinned_versions.insert(source, version.version);
+                }
+                Command::none()
+            }
+            Message::MoveSourceUp(value) => {
+                // selected sources double as the auto apply priority order
+                if let Some(idx) = self.selected.iter().position(|s| *s == value) {
+                    if idx > 0 {
+                        self.selected.swap(idx, idx - 1);
+                    }
+                }
+                Command::none()
+            }
+            Message::OnAutoApplyDone(result) => {
+                println!(
+                    "auto applied {} from {}, rune page: {:?}, spells: {:?}",
+                    result.champ_name, result.source, result.rune_page, result.spells
+                );
+                Command::none()
+            }
+            Message::OnAutoApplyFailed(report) => {
+                self.report(report);
+                Command::none()
+            }
+            Message::OnLcuEvent(event) => {
+                println!("lcu event: {:?}", event);
+                match event {
+                    lcu::events::LcuEvent::PhaseChanged(phase) => {
+                        self.lcu_phase = phase;
+                    }
+                    lcu::events::LcuEvent::ChampionLocked {
+                        champion_id,
+                        position,
+                    } => {
+                        if !self.auto_apply || self.selected.is_empty() {
+                            return Command::none();
+                        }
+                        let client = match self.lcu_auth.as_ref().map(lcu::LcuClient::new) {
+                            Some(Ok(client)) => client,
+                            _ => return Command::none(),
+                        };
+                        return Command::perform(
+                            lcu::auto_apply::auto_apply(
+                                client,
+                                self.selected_specs(),
+                                champion_id,
+                                position,
+                            ),
+                            auto_apply_handler,
+                        );
+                    }
+                    lcu::events::LcuEvent::Disconnected => {
+                        // the client may come back on another port
+                        self.lcu_auth = None;
+                        self.lcu_phase = String::new();
+                    }
+                    _ => {}
                 }
                 Command::none()
             }
         }
     }
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    ToggleSource(bool, String),
+    OnInput(String),
+    OnClick,
+    OnFetchList(Vec<web::Source>),
+    OnReqFailed(ErrorReport),
+    OnApplyBuildDone,
+    OnApplyBuildFailed(ErrorReport),
+    OnApplyEvent(builds::progress::ApplyEvent),
+    CancelApply,
+    ToggleKeepOld(bool),
+    SelectTarget(builds::layout::Target),
+    ToggleSkipOtherPatches(bool),
+    OnSelectDir,
+    OnSelectSnapshot,
+    ExportSnapshot,
+    OnSnapshotExported(String),
+    Tick,
+    OnGetLcuAuth(lcu::LcuAuth),
+    OnLcuAuthFailed(ErrorReport),
+    OnLcuEvent(lcu::events::LcuEvent),
+    ToggleAutoApply(bool),
+    MoveSourceUp(String),
+    OnFetchVersions(String, Vec<web::SourceVersion>),
+    PinVersion(String, web::SourceVersion),
+    OnAutoApplyDone(lcu::auto_apply::AutoApplyResult),
+    OnAutoApplyFailed(ErrorReport),
+    OnToggleRunes,
+    ApplyRune(web::Rune),
+    OnApplyRuneDone(String),
+    OnApplyRuneFailed(ErrorReport),
+    OnFetchChampList(Vec<String>),
+    OnChampSearch(String),
+    SelectChamp(String),
+    OnFetchRunes(String, Vec<runes::SourceRune>),
+    RetryFailed,
+    ExportReport,
+    DismissReport,
+    ToggleErrors,
+    CopyErrors,
+    ClearErrors,
+}
+
+fn result_handler(ret: anyhow::Result<Vec<web::Source>>) -> Message {
+    match ret {
+        Ok(list) => Message::OnFetchList(list),
+        Err(e) => Message::OnReqFailed(ErrorReport::new("fetch source list", &e)),
+    }
+}
+
+fn fetch_versions(source: String) -> Command<Message> {
+    // only npm packages have published versions
+    if sources::is_custom(&source) {
+        return Command::none();
+    }
+    let npm_name = format!("@champ-r/{}", source);
+    Command::perform(web::fetch_source_versions(npm_name), move |ret| match ret {
+        Ok(versions) => Message::OnFetchVersions(source.clone(), versions),
+        Err(e) => Message::OnReqFailed(ErrorReport::new(
+            &format!("fetch versions of {}", source),
+            &e,
+        )),
+    })
+}
+
+fn apply_result_handler(ret: Result<Vec<builds::report::ChampResult>, ErrorReport>) -> Message {
+    match ret {
+        Ok(results) if !results.is_empty() && !results.iter().any(|r| r.ok) => {
+            let e = builds::nothing_written(&results);
+            Message::OnApplyBuildFailed(ErrorReport::new("apply builds", &e))
+        }
+        Ok(_) => Message::OnApplyBuildDone,
+        Err(report) => Message::OnApplyBuildFailed(report),
+    }
+}
+
+fn snapshot_export_handler(ret: anyhow::Result<String>) -> Message {
+    match ret {
+        Ok(file) => Message::OnSnapshotExported(file),
+        Err(e) => Message::OnReqFailed(ErrorReport::new("export snapshot", &e)),
+    }
+}
+
+fn lcu_auth_handler(ret: anyhow::Result<lcu::LcuAuth>) -> Message {
+    match ret {
+        Ok(s) => Message::OnGetLcuAuth(s),
+        Err(e) => Message::OnLcuAuthFailed(ErrorReport::new("connect to league client", &e)),
+    }
+}
+
+fn apply_rune_handler(ret: anyhow::Result<lcu::perks::PerkPage>) -> Message {
+    match ret {
+        Ok(page) => Message::OnApplyRuneDone(page.name),
+        Err(e) => Message::OnApplyRuneFailed(ErrorReport::new("apply rune", &e)),
+    }
+}
+
+fn auto_apply_handler(ret: anyhow::Result<lcu::auto_apply::AutoApplyResult>) -> Message {
+    match ret {
+        Ok(result) => Message::OnAutoApplyDone(result),
+        Err(e) => Message::OnAutoApplyFailed(ErrorReport::new("auto apply", &e)),
+    }
+}
+
+fn champ_list_handler(ret: anyhow::Result<web::ChampListResp>) -> Message {
+    match ret {
+        Ok(resp) => {
+            let mut names: Vec<String> = resp.data.into_keys().collect();
+            names.sort();
+            Message::OnFetchChampList(names)
+        }
+        Err(e) => Message::OnReqFailed(ErrorReport::new("fetch champion list", &e)),
+    }
+}
+
+impl Application for App {
+    type Executor = executor::Default;
+    type Message = Message;
+    type Flags = ();
+
+    fn new(_flags: ()) -> (Self, Command<Message>) {
+        let app = App::new();
+        let mut commands = vec![
+            Command::perform(sources::fetch_source_list(), result_handler),
+            // the only lookup allowed to prompt for elevation
+            Command::perform(lcu::discover(app.lol_dir.clone(), true), lcu_auth_handler),
+        ];
+        if app.show_runes {
+            commands.push(Command::perform(
+                web::fetch_latest_champ_list(),
+                champ_list_handler,
+            ));
+        }
+        (app, Command::batch(commands))
+    }
+
+    fn title(&self) -> String {
+        String::from("ChampR.rs")
+    }
+
+    fn update(&mut self, message: Self::Message) -> Command<Message> {
+        let command = self.handle(message);
+        self.save_settings();
+        command
+    }
 
     fn subscription(&self) -> Subscription<Message> {
-        time::every(std::time::Duration::from_secs(5)).map(|_| Message::Tick)
+        let mut subscriptions =
+            vec![time::every(std::time::Duration::from_secs(5)).map(|_| Message::Tick)];
+        if let Some(auth) = &self.lcu_auth {
+            subscriptions.push(lcu::events::subscription(auth.clone()).map(Message::OnLcuEvent));
+        }
+        if let Some(run) = &self.apply_run {
+            subscriptions.push(
+                builds::progress::subscription(
+                    run.id,
+                    run.sources.clone(),
+                    run.path.clone(),
+                    run.options.clone(),
+                    run.cancel.clone(),
+                )
+                .map(Message::OnApplyEvent),
+            );
+        }
+        Subscription::batch(subscriptions)
     }
 
     fn mode(&self) -> Mode {
-        todo!()
+        Mode::Windowed
     }
 
-    fn view(&mut self) -> Element<Message> {
+    fn view(&mut self) -> Element<'_, Message> {
         let title = Text::new("ChampR")
             .font(fonts::CINZEL_DECORATIVE)
             .size(40)
@@ -261,6 +922,32 @@ impl Application for App {
             .push(dir_input_label)
             .height(Length::Units(50));
 
+        let (snapshot_label, snapshot_text) = match &self.snapshot {
+            Some(file) => ("Go Online", file.as_str()),
+            None => ("Open Snapshot", "Builds from the network."),
+        };
+        let mut export_snapshot_btn =
+            Button::new(&mut self.export_snapshot_btn, Text::new("Export").size(16));
+        if self.snapshot.is_none() && !self.selected.is_empty() {
+            export_snapshot_btn = export_snapshot_btn.on_press(Message::ExportSnapshot);
+        }
+        let snapshot_row = Row::new()
+            .spacing(10)
+            .padding(4)
+            .align_items(Alignment::Center)
+            .push(
+                Button::new(&mut self.snapshot_btn, Text::new(snapshot_label).size(16))
+                    .on_press(Message::OnSelectSnapshot),
+            )
+            .push(export_snapshot_btn)
+            .push(
+                Text::new(snapshot_text)
+                    .size(14)
+                    .width(Length::Fill)
+                    .vertical_alignment(alignment::Vertical::Center),
+            )
+            .height(Length::Units(50));
+
         let search_label = Text::new("Filter:");
         let search_input = TextInput::new(
             &mut self.search_input,
@@ -282,6 +969,7 @@ impl Application for App {
             .spacing(10)
             .push(Container::new(title_row).center_x().width(Length::Fill))
             .push(dir_row)
+            .push(snapshot_row)
             .push(filter_row)
             .width(Length::FillPortion(1))
             .height(Length::Fill);
@@ -292,37 +980,249 @@ impl Application for App {
             .width(Length::Fill)
             .height(Length::Fill);
 
-        for i in self.items.iter() {
+        for i in self.items.iter_mut() {
             let label = i.label.to_string();
             let value = i.value.to_string();
-            let checked = self.selected.contains(&value);
+            let priority = self.selected.iter().position(|s| *s == value);
             let visible = label.contains(&self.search);
 
             if visible {
-                let cb = Checkbox::new(checked, label.to_uppercase(), move |checked| {
-                    Message::ToggleSource(checked, value.to_string())
+                let toggle_value = value.clone();
+                let cb = Checkbox::new(priority.is_some(), label.to_uppercase(), move |checked| {
+                    Message::ToggleSource(checked, toggle_value.to_string())
                 })
-                .text_size(16);
-                scrollable = scrollable.push(cb);
+                .text_size(16)
+                .width(Length::Fill);
+                let mut source_row = Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(cb);
+                let mut version_row = None;
+                if let Some(idx) = priority {
+                    source_row = source_row.push(Text::new(format!("#{}", idx + 1)).size(14));
+                    if idx > 0 {
+                        source_row = source_row.push(
+                            Button::new(&mut i.up_btn, Text::new("Up").size(14))
+                                .on_press(Message::MoveSourceUp(value.clone())),
+                        );
+                    }
+
+                    let mut options = vec![latest_version()];
+                    options.extend(i.versions.iter().cloned());
+                    let pinned = self.pinned_versions.get(&value);
+                    let selected = options
+                        .iter()
+                        .find(|v| Some(&v.version) == pinned)
+                        .cloned()
+                        .unwrap_or_else(latest_version);
+                    let pin_value = value.clone();
+                    let mut row = Row::new().spacing(10).align_items(Alignment::Center);
+                    // pins are kept for the network, a snapshot has one version
+                    row = match (&self.snapshot, i.versions.first()) {
+                        (Some(_), Some(v)) => row.push(Text::new(v.to_string()).size(14)),
+                        _ => row.push(
+                            PickList::new(&mut i.version_list, options, Some(selected), move |v| {
+                                Message::PinVersion(pin_value.clone(), v)
+                            })
+                            .text_size(14),
+                        ),
+                    };
+                    if let Some(applied) = self.applied_versions.get(&value) {
+                        row = row.push(Text::new(format!("applied {}", applied)).size(14));
+                    }
+                    version_row = Some(row);
+                }
+                scrollable = scrollable.push(source_row);
+                if let Some(row) = version_row {
+                    scrollable = scrollable.push(row);
+                }
             }
         }
         col = col.push(scrollable);
 
         let check_btn = Row::new()
+            .spacing(10)
             .padding(4)
             .height(Length::Units(50))
             .push(Checkbox::new(
                 self.keep_old,
                 "Keep old builds",
-                move |checked| Message::ToggleKeepOld(checked),
+                Message::ToggleKeepOld,
+            ))
+            .push(Checkbox::new(
+                self.auto_apply,
+                "Auto apply runes",
+                Message::ToggleAutoApply,
             ));
         col = col.push(check_btn);
 
+        let mut target_row = Row::new().spacing(10).padding(4);
+        for target in builds::layout::Target::ALL {
+            target_row = target_row.push(
+                Radio::new(
+                    target,
+                    target.label(),
+                    Some(self.target),
+                    Message::SelectTarget,
+                )
+                .size(16)
+                .text_size(16),
+            );
+        }
+        col = col.push(target_row);
+        col = col.push(Row::new().padding(4).push(Checkbox::new(
+            self.skip_other_patches,
+            "Skip builds for other patches",
+            Message::ToggleSkipOtherPatches,
+        )));
+
+        if self.apply_run.is_some() || self.progress.total > 0 {
+            let total = self.progress.total.max(1) as f32;
+            let mut progress_col = Column::new().spacing(4).padding(4).push(
+                ProgressBar::new(0.0..=total, self.progress.finished() as f32)
+                    .height(Length::Units(10)),
+            );
+            for s in self.progress.sources.iter() {
+                let name = if s.version.is_empty() {
+                    s.source.clone()
+                } else {
+                    format!("{}@{}", s.source, s.version)
+                };
+                progress_col = progress_col.push(
+                    Text::new(format!("{}: {} done, {} failed", name, s.done, s.failed)).size(14),
+                );
+                if !s.installed.is_empty() {
+                    progress_col = progress_col.push(
+                        Text::new(format!(
+                            "  built for patch {}, game is on {}",
+                            s.patch, s.installed
+                        ))
+                        .size(14)
+                        .color(Color::from_rgb8(242, 203, 5)),
+                    );
+                }
+            }
+            progress_col = progress_col
+                .push(Text::new(format!("{} KB written", self.progress.bytes / 1024)).size(14));
+            col = col.push(progress_col);
+        }
+
+        if let (Some(report), None) = (&self.last_report, &self.apply_run) {
+            let mut result_list = Scrollable::new(&mut self.report_scrollable)
+                .spacing(2)
+                .padding(4)
+                .height(Length::Units(120));
+            for s in builds::report::summarize(&report.results).iter() {
+                result_list = result_list.push(
+                    Text::new(format!(
+                        "{}: {} ok, {} failed",
+                        s.source,
+                        s.done,
+                        s.failed.len()
+                    ))
+                    .size(14),
+                );
+                for r in report.failures().filter(|r| r.source == s.source) {
+                    result_list = result_list.push(
+                        Text::new(format!("  {}: {}", r.champ_name, r.reason))
+                            .size(12)
+                            .color(Color::from_rgb8(220, 60, 60)),
+                    );
+                }
+            }
+
+            let mut retry_btn = Button::new(
+                &mut self.retry_failed_btn,
+                Text::new("Retry failed").size(14),
+            );
+            if report.failures().next().is_some() {
+                retry_btn = retry_btn.on_press(Message::RetryFailed);
+            }
+            let actions = Row::new()
+                .spacing(10)
+                .padding(4)
+                .push(retry_btn)
+                .push(
+                    Button::new(
+                        &mut self.export_report_btn,
+                        Text::new("Export JSON").size(14),
+                    )
+                    .on_press(Message::ExportReport),
+                )
+                .push(
+                    Button::new(&mut self.dismiss_report_btn, Text::new("Dismiss").size(14))
+                        .on_press(Message::DismissReport),
+                );
+            col = col.push(result_list).push(actions);
+        }
+
+        // the client being unreachable is a state rather than a failure,
+        // it stays visible next to the errors of the last apply
+        if let Some(lcu_error) = &self.lcu_error {
+            col = col.push(
+                Row::new().padding(4).push(
+                    Text::new(lcu_error.error.to_string())
+                        .size(14)
+                        .color(Color::from_rgb8(128, 128, 128)),
+                ),
+            );
+        }
+        if let Some(last) = self.errors.last() {
+            let toggle_label = if self.show_errors {
+                "Hide".to_string()
+            } else {
+                format!("Details ({})", self.errors.len())
+            };
+            let status_row = Row::new()
+                .spacing(10)
+                .padding(4)
+                .align_items(Alignment::Center)
+                .push(
+                    Text::new(last.to_string())
+                        .size(14)
+                        .color(Color::from_rgb8(220, 60, 60))
+                        .width(Length::Fill),
+                )
+                .push(
+                    Button::new(&mut self.error_btn, Text::new(toggle_label).size(14))
+                        .on_press(Message::ToggleErrors),
+                );
+            col = col.push(status_row);
+
+            if self.show_errors {
+                let mut error_list = Scrollable::new(&mut self.error_scrollable)
+                    .spacing(8)
+                    .padding(4)
+                    .height(Length::Units(160));
+                for report in self.errors.iter().rev() {
+                    error_list = error_list.push(Text::new(report.bug_report()).size(12));
+                }
+                let actions = Row::new()
+                    .spacing(10)
+                    .padding(4)
+                    .push(
+                        Button::new(&mut self.copy_errors_btn, Text::new("Copy").size(14))
+                            .on_press(Message::CopyErrors),
+                    )
+                    .push(
+                        Button::new(&mut self.clear_errors_btn, Text::new("Clear").size(14))
+                            .on_press(Message::ClearErrors),
+                    );
+                col = col.push(error_list).push(actions);
+            }
+        }
+
+        let apply_btn = match &self.apply_run {
+            Some(_) => {
+                Button::new(&mut self.btn, Text::new("Cancel")).on_press(Message::CancelApply)
+            }
+            None => Button::new(&mut self.btn, Text::new("Apply")).on_press(Message::OnClick),
+        };
         let ctrl_row = Row::new()
             .spacing(10)
             .padding(4)
             .height(Length::Units(50))
-            .push(Button::new(&mut self.btn, Text::new("Apply")).on_press(Message::OnClick))
+            .push(apply_btn)
             .push(
                 Button::new(&mut self.rune_ctrl_btn, Text::new("Show Runes"))
                     .on_press(Message::OnToggleRunes),
@@ -332,10 +1232,7 @@ impl Application for App {
         let mut row = Row::new().width(Length::Fill).height(Length::Fill);
         row = row.push(col);
         if self.show_runes {
-            let rune_col = Column::new()
-                .width(Length::FillPortion(1))
-                .height(Length::Fill);
-            row = row.push(rune_col);
+            row = row.push(self.rune_panel.view());
         }
 
         Container::new(row)
@@ -346,6 +1243,72 @@ impl Application for App {
     }
 }
 
+impl RunePanel {
+    fn view(&mut self) -> Element<'_, Message> {
+        let search_input = TextInput::new(
+            &mut self.champ_search_input,
+            "search champion",
+            &self.champ_search,
+            Message::OnChampSearch,
+        )
+        .padding(4);
+
+        let keyword = self.champ_search.to_lowercase();
+        let mut champ_list = Scrollable::new(&mut self.champ_scrollable)
+            .spacing(4)
+            .height(Length::Units(120));
+        for champ in self.champs.iter_mut() {
+            if !champ.name.to_lowercase().contains(&keyword) {
+                continue;
+            }
+            champ_list = champ_list.push(
+                Button::new(&mut champ.select_btn, Text::new(&champ.name).size(16))
+                    .width(Length::Fill)
+                    .on_press(Message::SelectChamp(champ.name.clone())),
+            );
+        }
+
+        let mut rune_list = Scrollable::new(&mut self.rune_scrollable)
+            .spacing(6)
+            .height(Length::Fill);
+        if !self.selected_champ.is_empty() {
+            rune_list = rune_list.push(Text::new(&self.selected_champ).size(20));
+        }
+        for (position, items) in self.groups.iter_mut() {
+            rune_list = rune_list.push(
+                Text::new(position.to_uppercase())
+                    .size(16)
+                    .color(Color::from_rgb8(242, 203, 5)),
+            );
+            for item in items.iter_mut() {
+                let detail = format!(
+                    "{}\n{} picks, {} win, score {:.1}",
+                    item.source, item.rune.pick_count, item.rune.win_rate, item.rune.score
+                );
+                let rune_row = Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(detail).size(14).width(Length::Fill))
+                    .push(
+                        Button::new(&mut item.apply_btn, Text::new("Apply").size(16))
+                            .on_press(Message::ApplyRune(item.rune.clone())),
+                    );
+                rune_list = rune_list.push(rune_row);
+            }
+        }
+
+        Column::new()
+            .spacing(10)
+            .padding(4)
+            .width(Length::FillPortion(1))
+            .height(Length::Fill)
+            .push(search_input)
+            .push(champ_list)
+            .push(rune_list)
+            .into()
+    }
+}
+
 #[derive(Default)]
 struct Variant {
     scrollable: scrollable::State,
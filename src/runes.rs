@@ -0,0 +1,118 @@
+use anyhow::Result;
+use futures::StreamExt;
+
+use crate::web;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRune {
+    pub source: String,
+    pub rune: web::Rune,
+}
+
+/// Fetches the runes of `champ_name` from every source. `sources` are specs
+/// like `op.gg@1.0.1`, so pinned versions are honored.
+pub async fn fetch_champ_runes(
+    sources: Vec<String>,
+    champ_name: String,
+) -> Result<Vec<SourceRune>> {
+    let tasks = sources.into_iter().map(|source| {
+        let champ_name = champ_name.clone();
+        async move {
+            let provider = match crate::sources::resolve(&source) {
+                Ok(provider) => provider,
+                Err(e) => {
+                    println!("[runes] {} skipped, {:#}", source, e);
+                    return vec![];
+                }
+            };
+            let source = provider.name().to_string();
+            let data = match provider.champ_data(&champ_name).await {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("[runes] {} skipped, {:#}", source, e);
+                    vec![]
+                }
+            };
+            data.into_iter()
+                .flat_map(|champ| champ.runes)
+                .map(|rune| SourceRune {
+                    source: source.clone(),
+                    rune,
+                })
+                .collect::<Vec<SourceRune>>()
+        }
+    });
+
+    let runes = futures::stream::iter(tasks)
+        .buffer_unordered(4)
+        .collect::<Vec<Vec<SourceRune>>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+    Ok(runes)
+}
+
+/// Groups runes by position, keeping the order positions first appear in.
+/// Runes in a group are sorted by score, best first.
+pub fn group_by_position(runes: Vec<SourceRune>) -> Vec<(String, Vec<SourceRune>)> {
+    let mut groups: Vec<(String, Vec<SourceRune>)> = vec![];
+    for r in runes.into_iter() {
+        match groups
+            .iter_mut()
+            .find(|(position, _)| *position == r.rune.position)
+        {
+            Some((_, items)) => items.push(r),
+            None => groups.push((r.rune.position.clone(), vec![r])),
+        }
+    }
+
+    for (_, items) in groups.iter_mut() {
+        items.sort_by(|a, b| {
+            b.rune
+                .score
+                .partial_cmp(&a.rune.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_rune(source: &str, position: &str, score: f64) -> SourceRune {
+        SourceRune {
+            source: source.to_string(),
+            rune: web::Rune {
+                position: position.to_string(),
+                score,
+                ..web::Rune::default()
+            },
+        }
+    }
+
+    #[test]
+    fn group_and_sort_by_score() {
+        let groups = group_by_position(vec![
+            make_rune("op.gg", "mid", 1.0),
+            make_rune("op.gg", "top", 5.0),
+            make_rune("lolalytics", "mid", 3.0),
+        ]);
+
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    "mid".to_string(),
+                    vec![
+                        make_rune("lolalytics", "mid", 3.0),
+                        make_rune("op.gg", "mid", 1.0)
+                    ]
+                ),
+                ("top".to_string(), vec![make_rune("op.gg", "top", 5.0)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    builds::layout::Target,
+    sources::SourceConfig,
+    web::{http::HttpSettings, mirror::Mirrors},
+};
+
+pub const SETTINGS_VERSION: u64 = 1;
+
+const APP_DIR: &str = "champr";
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub version: u64,
+    pub lol_dir: String,
+    /// Selected sources, in auto apply priority order.
+    pub selected: Vec<String>,
+    pub keep_old: bool,
+    pub target: Target,
+    pub skip_other_patches: bool,
+    /// Source to package version, sources not listed follow `latest`.
+    pub pinned_versions: BTreeMap<String, String>,
+    /// Package version each source was last applied from.
+    pub applied_versions: BTreeMap<String, String>,
+    /// Download mirrors, tried in order.
+    pub mirrors: Mirrors,
+    pub http: HttpSettings,
+    /// Sources served from a folder or a stats service instead of npm.
+    pub custom_sources: Vec<SourceConfig>,
+    pub show_runes: bool,
+    pub auto_apply: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            lol_dir: String::new(),
+            selected: vec![],
+            keep_old: false,
+            target: Target::Game,
+            skip_other_patches: false,
+            pinned_versions: BTreeMap::new(),
+            applied_versions: BTreeMap::new(),
+            mirrors: Mirrors::default(),
+            http: HttpSettings::default(),
+            custom_sources: vec![],
+            show_runes: false,
+            auto_apply: false,
+        }
+    }
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR).join(SETTINGS_FILE))
+}
+
+type Migration = fn(Value) -> Value;
+
+/// `MIGRATIONS[n]` upgrades a version `n` file to version `n + 1`. Added
+/// fields don't need a step, they fall back to their default.
+const MIGRATIONS: [Migration; SETTINGS_VERSION as usize] = [
+    // files written before settings were versioned
+    |value| value,
+];
+
+pub fn migrate(mut value: Value) -> Result<Settings> {
+    if !value.is_object() {
+        return Err(anyhow!("settings is not an object"));
+    }
+
+    let mut version = value["version"].as_u64().unwrap_or(0);
+    while version < SETTINGS_VERSION {
+        value = MIGRATIONS[version as usize](value);
+        version += 1;
+    }
+    value["version"] = Value::from(version);
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Loads settings from `path`. A missing file gives the defaults, a corrupt
+/// one is moved aside to `{path}.bak` so it can be inspected.
+pub fn load_from(path: &Path) -> Settings {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Settings::default(),
+    };
+
+    let ret = serde_json::from_str::<Value>(&content)
+        .map_err(anyhow::Error::from)
+        .and_then(migrate);
+    match ret {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("[settings] corrupt file {}, {:?}", path.display(), e);
+            let _ = fs::rename(path, path.with_extension("json.bak"));
+            Settings::default()
+        }
+    }
+}
+
+pub fn save_to(path: &Path, settings: &Settings) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // write then rename, so a crash never leaves a half written file
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(settings)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load() -> Settings {
+    match settings_path() {
+        Some(p) => load_from(&p),
+        None => Settings::default(),
+    }
+}
+
+pub fn save(settings: &Settings) -> Result<()> {
+    let p = settings_path().ok_or_else(|| anyhow!("no config dir on this platform"))?;
+    save_to(&p, settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn save_and_load() {
+        let dir = temp_dir("champr_settings_roundtrip");
+        let p = dir.join(SETTINGS_FILE);
+        assert_eq!(load_from(&p), Settings::default());
+
+        let settings = Settings {
+            lol_dir: "C:/Riot Games/League of Legends".to_string(),
+            selected: vec!["op.gg".to_string(), "lolalytics".to_string()],
+            keep_old: true,
+            pinned_versions: BTreeMap::from([("op.gg".to_string(), "1.0.1".to_string())]),
+            ..Settings::default()
+        };
+        save_to(&p, &settings).unwrap();
+        assert_eq!(load_from(&p), settings);
+    }
+
+    #[test]
+    fn migrate_unversioned_file() {
+        let settings = migrate(serde_json::json!({
+            "lolDir": "D:/LoL",
+            "unknownField": 1,
+        }))
+        .unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.lol_dir, "D:/LoL");
+        assert_eq!(settings.selected, Vec::<String>::new());
+    }
+
+    #[test]
+    fn fallback_on_corrupt_file() {
+        let dir = temp_dir("champr_settings_corrupt");
+        let p = dir.join(SETTINGS_FILE);
+        fs::create_dir_all(p.parent().unwrap()).unwrap();
+        fs::write(&p, "{ not json").unwrap();
+
+        assert_eq!(load_from(&p), Settings::default());
+        assert!(!p.exists());
+        assert!(p.with_extension("json.bak").exists());
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    sync::{PoisonError, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use futures::{future::BoxFuture, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{builds::parse_source_spec, web};
+
+pub mod http_json;
+pub mod local;
+pub mod npm;
+
+pub use http_json::HttpJsonSource;
+pub use local::LocalSource;
+pub use npm::NpmSource;
+
+/// Where builds of a source come from. `name` is what ends up in item set
+/// file names, the manifest and the settings, so it has to stay stable.
+pub trait BuildSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Label and game modes, as shown in the source list.
+    fn meta(&self) -> web::Source;
+
+    /// Champions the source has data for, by the names it uses for them.
+    fn list_champs(&self) -> BoxFuture<'_, Result<Vec<String>>>;
+
+    fn champ_data<'a>(&'a self, champ_name: &'a str) -> BoxFuture<'a, Result<Vec<web::ChampData>>>;
+
+    /// Builds of `champ_names`, or of every champion from `list_champs` when
+    /// empty. Champions without data are left out. Providers that can fetch
+    /// everything in one go override this.
+    fn package<'a>(
+        &'a self,
+        champ_names: &'a [String],
+    ) -> BoxFuture<'a, Result<web::SourcePackage>> {
+        Box::pin(async move {
+            let champ_names = if champ_names.is_empty() {
+                self.list_champs().await?
+            } else {
+                champ_names.to_vec()
+            };
+            let tasks = champ_names.into_iter().map(|champ_name| async move {
+                let ret = self.champ_data(&champ_name).await;
+                (champ_name, ret)
+            });
+            let fetched = futures::stream::iter(tasks)
+                .buffer_unordered(8)
+                .collect::<Vec<_>>()
+                .await;
+
+            let mut champs = HashMap::new();
+            for (champ_name, ret) in fetched.into_iter() {
+                match ret {
+                    Ok(data) if !data.is_empty() => {
+                        champs.insert(champ_name, data);
+                    }
+                    Ok(_) => {}
+                    Err(e) => println!("[{}] no data for {}, {:#}", self.name(), champ_name, e),
+                }
+            }
+            if champs.is_empty() {
+                return Err(anyhow!("{} has no champion data", self.name()));
+            }
+            Ok(make_package(String::new(), champs))
+        })
+    }
+}
+
+/// Refuses champion names that could point outside a folder or URL path,
+/// they come from list files the provider controls.
+pub fn check_champ_name(champ_name: &str) -> Result<()> {
+    if champ_name.is_empty() || champ_name.contains(['/', '\\']) || champ_name.contains("..") {
+        return Err(anyhow!("invalid champion name: {:?}", champ_name));
+    }
+    Ok(())
+}
+
+/// Version and patch of a package put together from single champion files,
+/// `version` wins over the versions found in the data.
+pub fn make_package(
+    version: String,
+    champs: HashMap<String, Vec<web::ChampData>>,
+) -> web::SourcePackage {
+    let first = |f: fn(&web::ChampData) -> &String| {
+        champs
+            .values()
+            .flatten()
+            .map(f)
+            .find(|v| !v.is_empty())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let version = if version.is_empty() {
+        first(|c| &c.version)
+    } else {
+        version
+    };
+    let patch = first(|c| &c.official_version);
+    web::SourcePackage {
+        version,
+        patch,
+        champs,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ProviderConfig {
+    /// A folder laid out like an unpacked source package: one
+    /// `{champ_name}.json` per champion, and optionally `package.json`.
+    Local { dir: String },
+    /// A service answering `champ_url` with the `ChampData` list of a
+    /// champion. `{source}` and `{champ}` in the URL are filled in.
+    /// `list_url` answers a JSON array of champion names, without it the
+    /// champions on DDragon are asked for.
+    #[serde(rename_all = "camelCase")]
+    HttpJson {
+        champ_url: String,
+        #[serde(default)]
+        list_url: String,
+    },
+}
+
+/// A source that is not published on npm, added in the settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceConfig {
+    pub name: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub is_aram: bool,
+    #[serde(default)]
+    pub is_urf: bool,
+    pub provider: ProviderConfig,
+}
+
+impl SourceConfig {
+    pub fn meta(&self) -> web::Source {
+        web::Source {
+            label: if self.label.is_empty() {
+                self.name.clone()
+            } else {
+                self.label.clone()
+            },
+            value: self.name.clone(),
+            is_aram: Some(self.is_aram),
+            is_urf: Some(self.is_urf),
+        }
+    }
+
+    pub fn provider(&self) -> Box<dyn BuildSource> {
+        match &self.provider {
+            ProviderConfig::Local { dir } => Box::new(LocalSource::new(self.meta(), dir.into())),
+            ProviderConfig::HttpJson {
+                champ_url,
+                list_url,
+            } => Box::new(HttpJsonSource::new(
+                self.meta(),
+                champ_url.clone(),
+                list_url.clone(),
+            )),
+        }
+    }
+}
+
+lazy_static! {
+    static ref CUSTOM: RwLock<Vec<SourceConfig>> = RwLock::new(vec![]);
+}
+
+/// Replaces the sources added in the settings.
+pub fn set_custom_sources(sources: Vec<SourceConfig>) {
+    *CUSTOM.write().unwrap_or_else(PoisonError::into_inner) = sources;
+}
+
+pub fn custom_sources() -> Vec<SourceConfig> {
+    CUSTOM
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+pub fn is_custom(name: &str) -> bool {
+    custom_sources().iter().any(|c| c.name == name)
+}
+
+/// Provider of a source spec such as `op.gg@1.0.1`. Sources from the
+/// settings take precedence, anything else is an `@champ-r` npm package.
+/// Sources from the settings have no versions, pinning one is an error.
+pub fn resolve(spec: &str) -> Result<Box<dyn BuildSource>> {
+    let (name, version) = parse_source_spec(spec);
+    match custom_sources().into_iter().find(|c| c.name == name) {
+        Some(_) if version != "latest" => Err(anyhow!(
+            "{} is not an npm package, it can't be pinned to {}",
+            name,
+            version
+        )),
+        Some(config) => Ok(config.provider()),
+        None => Ok(Box::new(NpmSource::new(name, version))),
+    }
+}
+
+/// The published source list followed by the sources from the settings.
+/// Only fails when there is nothing to show.
+pub async fn fetch_source_list() -> Result<Vec<web::Source>> {
+    let custom: Vec<web::Source> = custom_sources().iter().map(SourceConfig::meta).collect();
+    match web::fetch_source_list().await {
+        Ok(mut list) => {
+            list.retain(|s| !custom.iter().any(|c| c.value == s.value));
+            list.extend(custom);
+            Ok(list)
+        }
+        Err(e) if !custom.is_empty() => {
+            println!("[sources] source list unavailable, {:?}", e);
+            Ok(custom)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let list: Vec<SourceConfig> = serde_json::from_str(
+            r#"[
+                {"name": "team", "label": "Team stats", "isAram": true,
+                 "provider": {"kind": "httpJson", "champUrl": "https://stats.lan/{champ}.json"}},
+                {"name": "usb", "provider": {"kind": "local", "dir": "/media/usb/builds"}}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            list[0].provider,
+            ProviderConfig::HttpJson {
+                champ_url: "https://stats.lan/{champ}.json".to_string(),
+                list_url: String::new(),
+            }
+        );
+        assert_eq!(list[1].meta().label, "usb");
+
+        let provider = list[0].provider();
+        assert_eq!(provider.name(), "team");
+        assert_eq!(provider.meta().is_aram, Some(true));
+
+        set_custom_sources(list);
+        assert_eq!(resolve("usb").unwrap().name(), "usb");
+        assert!(resolve("usb@1.0.0").is_err());
+        assert_eq!(resolve("op.gg@1.0.0").unwrap().name(), "op.gg");
+        set_custom_sources(vec![]);
+    }
+
+    #[test]
+    fn package_versions() {
+        let mut champs = HashMap::new();
+        champs.insert(
+            "Annie".to_string(),
+            vec![web::ChampData {
+                version: "1.2.0".to_string(),
+                official_version: "12.10.1".to_string(),
+                ..web::ChampData::default()
+            }],
+        );
+        let package = make_package(String::new(), champs.clone());
+        assert_eq!(package.version, "1.2.0");
+        assert_eq!(package.patch, "12.10.1");
+        assert_eq!(make_package("2.0.0".to_string(), champs).version, "2.0.0");
+    }
+}
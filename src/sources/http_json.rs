@@ -0,0 +1,132 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+
+use super::{check_champ_name, BuildSource};
+use crate::{error::Error, web, web::cache};
+
+/// Characters left as they are in a URL path segment, RFC 3986 unreserved.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A stats service that serves one champion per request, see
+/// `ProviderConfig::HttpJson`.
+pub struct HttpJsonSource {
+    meta: web::Source,
+    champ_url: String,
+    list_url: String,
+}
+
+impl HttpJsonSource {
+    pub fn new(meta: web::Source, champ_url: String, list_url: String) -> Self {
+        Self {
+            meta,
+            champ_url,
+            list_url,
+        }
+    }
+
+    /// `champ_url` with `{source}` and `{champ}` filled in, percent-encoded.
+    pub fn champ_url(&self, champ_name: &str) -> String {
+        let source = utf8_percent_encode(&self.meta.value, SEGMENT).to_string();
+        let champ = utf8_percent_encode(champ_name, SEGMENT).to_string();
+        self.champ_url
+            .replace("{source}", &source)
+            .replace("{champ}", &champ)
+    }
+
+    /// Goes through the on-disk cache like every other download, so the
+    /// last answer is still there when the service is down.
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let buf = cache::get_url(url).await?;
+        serde_json::from_slice(&buf).map_err(|e| Error::Decode(format!("{}, {}", url, e)).into())
+    }
+}
+
+impl BuildSource for HttpJsonSource {
+    fn name(&self) -> &str {
+        &self.meta.value
+    }
+
+    fn meta(&self) -> web::Source {
+        self.meta.clone()
+    }
+
+    fn list_champs(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            if self.list_url.is_empty() {
+                let champ_list = web::fetch_latest_champ_list().await?;
+                let mut names: Vec<String> = champ_list.data.into_keys().collect();
+                names.sort();
+                return Ok(names);
+            }
+            self.get_json(&self.list_url).await
+        })
+    }
+
+    fn champ_data<'a>(&'a self, champ_name: &'a str) -> BoxFuture<'a, Result<Vec<web::ChampData>>> {
+        Box::pin(async move {
+            check_champ_name(champ_name)?;
+            self.get_json(&self.champ_url(champ_name)).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Response, StubServer};
+
+    #[tokio::test]
+    async fn fetch_from_template() {
+        let server = StubServer::http(|req| match req.path.as_str() {
+            "/champs" => Response::json(200, r#"["Annie", "Zed"]"#),
+            "/team/Annie.json" => Response::json(
+                200,
+                r#"[{"index": 0, "id": "Annie", "version": "7", "officialVersion": "12.10.1",
+                     "timestamp": 0, "alias": "annie", "name": "Annie", "position": "mid",
+                     "itemBuilds": [], "runes": []}]"#,
+            ),
+            _ => Response::status(404),
+        })
+        .await;
+
+        let meta = web::Source {
+            label: "Team stats".to_string(),
+            value: "team".to_string(),
+            is_aram: None,
+            is_urf: None,
+        };
+        let source = HttpJsonSource::new(
+            meta,
+            format!("{}/{{source}}/{{champ}}.json", server.url()),
+            format!("{}/champs", server.url()),
+        );
+        assert_eq!(
+            source.champ_url("Annie"),
+            format!("{}/team/Annie.json", server.url())
+        );
+        assert_eq!(
+            source.champ_url("Kai'Sa #2"),
+            format!("{}/team/Kai%27Sa%20%232.json", server.url())
+        );
+        assert!(source.champ_data("../admin").await.is_err());
+        assert_eq!(source.list_champs().await.unwrap(), vec!["Annie", "Zed"]);
+
+        let err = source.champ_data("Zed").await.unwrap_err();
+        assert!(matches!(
+            crate::error::classify(&err),
+            Error::HttpStatus { status: 404, .. }
+        ));
+
+        // champions the service has nothing for are left out
+        let package = source.package(&[]).await.unwrap();
+        assert_eq!(package.version, "7");
+        assert_eq!(package.patch, "12.10.1");
+        assert_eq!(package.champs.keys().collect::<Vec<_>>(), vec!["Annie"]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+
+use super::{check_champ_name, make_package, BuildSource};
+use crate::{error::Error, web};
+
+const PACKAGE_FILE: &str = "package.json";
+
+/// A folder laid out like an unpacked source package, e.g. on a USB stick.
+pub struct LocalSource {
+    meta: web::Source,
+    dir: PathBuf,
+}
+
+impl LocalSource {
+    pub fn new(meta: web::Source, dir: PathBuf) -> Self {
+        Self { meta, dir }
+    }
+
+    /// `version` of `package.json`, empty when there is none.
+    fn package_version(&self) -> String {
+        fs::read(self.dir.join(PACKAGE_FILE))
+            .ok()
+            .and_then(|buf| serde_json::from_slice::<web::NpmInfo>(&buf).ok())
+            .map(|info| info.version)
+            .unwrap_or_default()
+    }
+}
+
+impl BuildSource for LocalSource {
+    fn name(&self) -> &str {
+        &self.meta.value
+    }
+
+    fn meta(&self) -> web::Source {
+        self.meta.clone()
+    }
+
+    fn list_champs(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            let mut names = vec![];
+            for entry in fs::read_dir(&self.dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                    continue;
+                }
+                match path.file_stem().and_then(|stem| stem.to_str()) {
+                    Some("package") | Some("index") | None => continue,
+                    Some(stem) => names.push(stem.to_string()),
+                }
+            }
+            names.sort();
+            Ok(names)
+        })
+    }
+
+    fn champ_data<'a>(&'a self, champ_name: &'a str) -> BoxFuture<'a, Result<Vec<web::ChampData>>> {
+        Box::pin(async move {
+            check_champ_name(champ_name)?;
+            let p = self.dir.join(format!("{}.json", champ_name));
+            let buf = fs::read(&p)?;
+            serde_json::from_slice(&buf)
+                .map_err(|e| Error::Decode(format!("{}, {}", p.display(), e)).into())
+        })
+    }
+
+    /// Every file in the folder, whatever names it uses for champions.
+    fn package<'a>(
+        &'a self,
+        _champ_names: &'a [String],
+    ) -> BoxFuture<'a, Result<web::SourcePackage>> {
+        Box::pin(async move {
+            let mut champs = HashMap::new();
+            for champ_name in self.list_champs().await? {
+                match self.champ_data(&champ_name).await {
+                    Ok(data) if !data.is_empty() => {
+                        champs.insert(champ_name, data);
+                    }
+                    Ok(_) => {}
+                    Err(e) => println!("[{}] skipped {}, {:#}", self.name(), champ_name, e),
+                }
+            }
+            if champs.is_empty() {
+                return Err(anyhow!(
+                    "{} has no champion data in {}",
+                    self.name(),
+                    self.dir.display()
+                ));
+            }
+            Ok(make_package(self.package_version(), champs))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[tokio::test]
+    async fn read_folder() {
+        let dir = temp_dir("champr_local_source");
+        fs::write(dir.join("package.json"), r#"{"version": "3.1.0"}"#).unwrap();
+        fs::write(
+            dir.join("Annie.json"),
+            r#"[{"index": 0, "id": "Annie", "version": "", "officialVersion": "12.10.1",
+                "timestamp": 0, "alias": "annie", "name": "Annie", "position": "mid",
+                "itemBuilds": [], "runes": []}]"#,
+        )
+        .unwrap();
+        fs::write(dir.join("Zed.json"), "not json").unwrap();
+        fs::write(dir.join("Ahri.json"), "[]").unwrap();
+
+        let meta = web::Source {
+            label: "USB".to_string(),
+            value: "usb".to_string(),
+            is_aram: None,
+            is_urf: None,
+        };
+        let source = LocalSource::new(meta, dir.to_path_buf());
+        assert_eq!(
+            source.list_champs().await.unwrap(),
+            vec!["Ahri", "Annie", "Zed"]
+        );
+
+        let err = source.champ_data("Zed").await.unwrap_err();
+        assert!(matches!(crate::error::classify(&err), Error::Decode(_)));
+        assert!(source.champ_data("../package").await.is_err());
+        assert!(source.champ_data("sub/Annie").await.is_err());
+
+        let package = source.package(&[]).await.unwrap();
+        assert_eq!(package.version, "3.1.0");
+        assert_eq!(package.patch, "12.10.1");
+        assert_eq!(package.champs.len(), 1);
+        assert_eq!(package.champs["Annie"][0].position, "mid");
+
+        // an empty or unplugged folder must not wipe the builds it replaces
+        fs::remove_file(source.dir.join("Annie.json")).unwrap();
+        assert!(source.package(&[]).await.is_err());
+    }
+}
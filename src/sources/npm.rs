@@ -0,0 +1,66 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use super::BuildSource;
+use crate::web;
+
+/// A source published as the `@champ-r/{name}` npm package, fetched through
+/// the npm mirrors.
+pub struct NpmSource {
+    name: String,
+    /// Exact package version or a dist tag such as `latest`.
+    version: String,
+}
+
+impl NpmSource {
+    pub fn new(name: String, version: String) -> Self {
+        Self { name, version }
+    }
+
+    fn npm_name(&self) -> String {
+        format!("@champ-r/{}", self.name)
+    }
+}
+
+impl BuildSource for NpmSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn meta(&self) -> web::Source {
+        web::Source {
+            label: self.name.clone(),
+            value: self.name.clone(),
+            is_aram: None,
+            is_urf: None,
+        }
+    }
+
+    fn list_champs(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            let package = self.package(&[]).await?;
+            let mut names: Vec<String> = package.champs.into_keys().collect();
+            names.sort();
+            Ok(names)
+        })
+    }
+
+    fn champ_data<'a>(&'a self, champ_name: &'a str) -> BoxFuture<'a, Result<Vec<web::ChampData>>> {
+        Box::pin(web::fetch_champ_detail(
+            self.npm_name(),
+            self.version.clone(),
+            champ_name.to_string(),
+        ))
+    }
+
+    /// The whole package comes as one tarball, `champ_names` is ignored.
+    fn package<'a>(
+        &'a self,
+        _champ_names: &'a [String],
+    ) -> BoxFuture<'a, Result<web::SourcePackage>> {
+        Box::pin(web::fetch_source_tarball(
+            self.npm_name(),
+            self.version.clone(),
+        ))
+    }
+}
@@ -0,0 +1,253 @@
+//! Local HTTP(S) stub server used by tests in place of the CDN, the npm
+//! registry and the League client.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    net::SocketAddr,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+/// A folder under the system temp dir, removed again when dropped.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A new empty folder under the system temp dir. The name gets the process
+/// id and a counter, so parallel test runs never share a folder.
+pub fn temp_dir(name: &str) -> TempDir {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNT.fetch_add(1, Ordering::SeqCst);
+    let dir = env::temp_dir().join(format!("{}_{}_{}", name, process::id(), n));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    TempDir(dir)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            ..Self::default()
+        }
+    }
+
+    pub fn json(status: u16, body: &str) -> Self {
+        Self::bytes(status, body.as_bytes().to_vec())
+            .with_header("Content-Type", "application/json")
+    }
+
+    pub fn bytes(status: u16, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+pub struct StubServer {
+    pub addr: SocketAddr,
+    pub tls: bool,
+    /// Certificate served over TLS, empty for plain HTTP.
+    cert_pem: Vec<u8>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl StubServer {
+    pub async fn http<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        Self::start(Arc::new(handler), None).await
+    }
+
+    /// Serves over TLS with a freshly generated self-signed certificate,
+    /// like the League client does.
+    pub async fn https<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let cert = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let cert_pem = cert.serialize_pem().unwrap();
+        let identity = native_tls::Identity::from_pkcs8(
+            cert_pem.as_bytes(),
+            cert.serialize_private_key_pem().as_bytes(),
+        )
+        .unwrap();
+        let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+        let mut server = Self::start(
+            Arc::new(handler),
+            Some(tokio_native_tls::TlsAcceptor::from(acceptor)),
+        )
+        .await;
+        server.cert_pem = cert_pem.into_bytes();
+        server
+    }
+
+    async fn start(handler: Handler, acceptor: Option<tokio_native_tls::TlsAcceptor>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let tls = acceptor.is_some();
+
+        let log = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let log = log.clone();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    match acceptor {
+                        Some(acceptor) => {
+                            if let Ok(stream) = acceptor.accept(stream).await {
+                                serve(stream, handler, log).await;
+                            }
+                        }
+                        None => serve(stream, handler, log).await,
+                    }
+                });
+            }
+        });
+
+        Self {
+            addr,
+            tls,
+            cert_pem: vec![],
+            requests,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}", scheme, self.addr)
+    }
+
+    pub fn cert_pem(&self) -> &[u8] {
+        &self.cert_pem
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn serve<S>(stream: S, handler: Handler, log: Arc<Mutex<Vec<Request>>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+        return;
+    }
+    let mut parts = line.split_whitespace();
+    let mut req = Request {
+        method: parts.next().unwrap_or_default().to_string(),
+        path: parts.next().unwrap_or_default().to_string(),
+        ..Request::default()
+    };
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            req.headers
+                .insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let len = req
+        .headers
+        .get("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; len];
+    if reader.read_exact(&mut body).await.is_err() {
+        return;
+    }
+    req.body = body;
+
+    let resp = handler(&req);
+    log.lock().unwrap().push(req);
+
+    let mut head = format!(
+        "HTTP/1.1 {} STUB\r\nContent-Length: {}\r\nConnection: close\r\n",
+        resp.status,
+        resp.body.len()
+    );
+    for (name, value) in resp.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let stream = reader.get_mut();
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&resp.body).await;
+    let _ = stream.shutdown().await;
+}
@@ -1,14 +1,24 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io::Read};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use tar::Archive;
+
+use crate::error::Error;
+
+pub mod cache;
+pub mod http;
+pub mod mirror;
+
+use mirror::Resource;
 
 pub const CDN_JSDELIVR: &str = "https://cdn.jsdelivr.net";
 pub const NPM_MIRROR: &str = "https://registry.npmmirror.com";
 pub const CDN_DDRAGON: &str = "https://ddragon.leagueoflegends.com";
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Source {
     pub label: String,
@@ -18,12 +28,8 @@ pub struct Source {
 }
 
 pub async fn fetch_source_list() -> Result<Vec<Source>> {
-    let url = format!(
-        "{cdn}/gh/champ-r/source-list/index.json",
-        cdn = CDN_JSDELIVR
-    );
-    let resp = reqwest::get(url).await?;
-    let data = resp.json::<Vec<Source>>().await?;
+    let buf = cache::get(Resource::SourceList, "/index.json").await?;
+    let data = serde_json::from_slice::<Vec<Source>>(&buf)?;
     Ok(data)
 }
 
@@ -93,32 +99,22 @@ pub async fn fetch_champ_detail(
     source: String,
     version: String,
     champ_name: String,
-) -> Result<Option<Vec<ChampData>>> {
+) -> Result<Vec<ChampData>> {
     let url = format!(
-        "{cdn}/npm/{source}@{version}/{champ_name}.json",
-        cdn = CDN_JSDELIVR,
+        "/{source}@{version}/{champ_name}.json",
         source = &source,
         version = &version,
         champ_name = &champ_name
     );
     println!("fetching champ detail: [{}]", url);
 
-    let resp = reqwest::get(&url).await?;
-    if !resp.status().is_success() {
-        println!("[champ detail] request failed, {} {}", source, champ_name);
-    }
-
-    match resp.json::<Vec<ChampData>>().await {
-        Ok(data) => Ok(Some(data)),
-        Err(e) => {
-            println!("[{}], {:?}", url, e.to_string());
-            Ok(None)
-        }
-    }
+    let buf = cache::get(Resource::Npm, &url).await?;
+    serde_json::from_slice::<Vec<ChampData>>(&buf)
+        .map_err(|e| Error::Decode(format!("{}, {}", url, e)).into())
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct NpmInfo {
     pub name: String,
     pub version: String,
@@ -127,6 +123,14 @@ pub struct NpmInfo {
     pub main: String,
     #[serde(rename = "dist-tags")]
     pub dist_tags: DistTags,
+    pub dist: Dist,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dist {
+    pub tarball: String,
+    pub shasum: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -135,17 +139,145 @@ pub struct DistTags {
     pub latest: String,
 }
 
-pub async fn fetch_npm_info(source: String) -> Result<NpmInfo> {
-    let url = format!("{cdn}/{source}/latest", cdn = NPM_MIRROR, source = &source);
-    let resp = reqwest::get(url).await?;
-    let data = resp.json::<NpmInfo>().await?;
+/// `version` is an exact version or a dist tag such as `latest`.
+pub async fn fetch_npm_info(source: String, version: String) -> Result<NpmInfo> {
+    let url = format!("/{source}/{version}", source = &source, version = &version);
+    let buf = cache::get(Resource::Registry, &url).await?;
+    let data = serde_json::from_slice::<NpmInfo>(&buf)?;
     Ok(data)
 }
 
+/// Registry document of a package, listing every published version.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NpmPackage {
+    #[serde(rename = "dist-tags")]
+    pub dist_tags: DistTags,
+    pub versions: HashMap<String, NpmInfo>,
+    pub time: HashMap<String, String>,
+}
+
+/// A published version of a source, and the LoL patch its data is for.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceVersion {
+    pub version: String,
+    pub patch: String,
+    pub published: String,
+}
+
+impl std::fmt::Display for SourceVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.patch.is_empty() {
+            write!(f, "{}", self.version)
+        } else {
+            write!(f, "{} (patch {})", self.version, self.patch)
+        }
+    }
+}
+
+/// Versions of a package, newest first.
+pub fn list_versions(package: &NpmPackage) -> Vec<SourceVersion> {
+    let mut versions: Vec<SourceVersion> = package
+        .versions
+        .values()
+        .map(|info| SourceVersion {
+            version: info.version.clone(),
+            patch: info.source_version.clone(),
+            published: package.time.get(&info.version).cloned().unwrap_or_default(),
+        })
+        .collect();
+    versions.sort_by(|a, b| {
+        b.published
+            .cmp(&a.published)
+            .then_with(|| b.version.cmp(&a.version))
+    });
+    versions
+}
+
+pub async fn fetch_source_versions(source: String) -> Result<Vec<SourceVersion>> {
+    let url = format!("/{source}", source = &source);
+    let buf = cache::get(Resource::Registry, &url).await?;
+    let data = serde_json::from_slice::<NpmPackage>(&buf)?;
+    Ok(list_versions(&data))
+}
+
+/// Builds of one source at the version that was actually downloaded.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SourcePackage {
+    pub version: String,
+    /// LoL patch of the data, from `officialVersion` of the builds or the
+    /// package's `sourceVersion`.
+    pub patch: String,
+    pub champs: HashMap<String, Vec<ChampData>>,
+}
+
+/// Downloads the whole npm package of a source in one request. `version` is
+/// an exact version or `latest`.
+pub async fn fetch_source_tarball(source: String, version: String) -> Result<SourcePackage> {
+    let info = fetch_npm_info(source.clone(), version).await?;
+    if info.dist.tarball.is_empty() {
+        return Err(anyhow!("no tarball found for {}", source));
+    }
+    println!("fetching tarball: [{}]", info.dist.tarball);
+
+    // registries share the tarball layout, so any of them can serve it
+    let path = mirror::pool()?.strip_base(Resource::Registry, &info.dist.tarball);
+    let buf = match path {
+        Some(path) => cache::get(Resource::Registry, &path).await,
+        None => cache::get_url(&info.dist.tarball).await,
+    }
+    .map_err(|e| anyhow!("[tarball] request failed, {} {:?}", source, e))?;
+    let champs = unpack_source_tarball(&buf)?;
+
+    let patch = champs
+        .values()
+        .flatten()
+        .map(|c| c.official_version.clone())
+        .find(|v| !v.is_empty())
+        .unwrap_or(info.source_version);
+    Ok(SourcePackage {
+        version: info.version,
+        patch,
+        champs,
+    })
+}
+
+/// Unpacks a gzipped npm tarball in memory. Every `{champ_name}.json` in the
+/// package is parsed as `Vec<ChampData>`, other files are ignored.
+pub fn unpack_source_tarball(buf: &[u8]) -> Result<HashMap<String, Vec<ChampData>>> {
+    let mut archive = Archive::new(GzDecoder::new(buf));
+    let mut champs = HashMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let champ_name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some("package") | Some("index") | None => continue,
+            Some(stem) => stem.to_string(),
+        };
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        match serde_json::from_str::<Vec<ChampData>>(&content) {
+            Ok(data) => {
+                champs.insert(champ_name, data);
+            }
+            Err(e) => {
+                println!("[tarball] skipped {}, {:?}", path.display(), e.to_string());
+            }
+        }
+    }
+
+    Ok(champs)
+}
+
 pub async fn fetch_lol_version_list() -> Result<Vec<String>> {
-    let url = format!("{cdn}/api/versions.json", cdn = CDN_DDRAGON);
-    let resp = reqwest::get(url).await?;
-    let data = resp.json::<Vec<String>>().await?;
+    let buf = cache::get(Resource::Ddragon, "/api/versions.json").await?;
+    let data = serde_json::from_slice::<Vec<String>>(&buf)?;
     Ok(data)
 }
 
@@ -190,11 +322,103 @@ pub struct Image {
 
 pub async fn fetch_champ_list(version: String) -> Result<ChampListResp> {
     let url = format!(
-        "{cdn}/cdn/{version}/data/en_US/champion.json",
-        cdn = CDN_DDRAGON,
+        "/cdn/{version}/data/en_US/champion.json",
         version = &version
     );
-    let resp = reqwest::get(url).await?;
-    let data = resp.json::<ChampListResp>().await?;
+    let buf = cache::get(Resource::Ddragon, &url).await?;
+    let data = serde_json::from_slice::<ChampListResp>(&buf)?;
     Ok(data)
 }
+
+/// Maps the numeric champion id used by the client to its DDragon name.
+pub fn find_champ_name(champ_list: &ChampListResp, champion_id: u64) -> Option<String> {
+    let key = champion_id.to_string();
+    champ_list
+        .data
+        .values()
+        .find(|c| c.key == key)
+        .map(|c| c.id.clone())
+}
+
+/// Champion list of the latest patch on DDragon.
+pub async fn fetch_latest_champ_list() -> Result<ChampListResp> {
+    let v = fetch_lol_version_list().await?;
+    let version = v
+        .first()
+        .ok_or_else(|| anyhow!("fetch lol version failed"))?;
+    fetch_champ_list(version.to_string()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    fn make_tarball(files: Vec<(&str, String)>) -> Vec<u8> {
+        let encoder = GzEncoder::new(vec![], Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn unpack_tarball() {
+        let champ = ChampData {
+            alias: "Annie".to_string(),
+            name: "Annie".to_string(),
+            position: "mid".to_string(),
+            item_builds: vec![ItemBuild::default()],
+            ..ChampData::default()
+        };
+        let buf = make_tarball(vec![
+            (
+                "package/package.json",
+                r#"{"name":"@champ-r/op.gg"}"#.to_string(),
+            ),
+            (
+                "package/Annie.json",
+                serde_json::to_string(&vec![champ.clone()]).unwrap(),
+            ),
+            ("package/Broken.json", "not json".to_string()),
+            ("package/README.md", "# readme".to_string()),
+        ]);
+
+        let champs = unpack_source_tarball(&buf).unwrap();
+        assert_eq!(champs.len(), 1);
+        assert_eq!(champs.get("Annie"), Some(&vec![champ]));
+    }
+
+    #[test]
+    fn sort_versions() {
+        let package: NpmPackage = serde_json::from_str(
+            r#"{
+                "dist-tags": {"latest": "1.0.2"},
+                "versions": {
+                    "1.0.1": {"version": "1.0.1", "sourceVersion": "12.9.1"},
+                    "1.0.2": {"version": "1.0.2", "sourceVersion": "12.10.1"},
+                    "1.0.0": {"version": "1.0.0"}
+                },
+                "time": {
+                    "1.0.0": "2022-05-01T00:00:00.000Z",
+                    "1.0.1": "2022-05-10T00:00:00.000Z",
+                    "1.0.2": "2022-05-20T00:00:00.000Z"
+                }
+            }"#,
+        )
+        .unwrap();
+        let versions = list_versions(&package);
+        let names: Vec<String> = versions.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            names,
+            vec!["1.0.2 (patch 12.10.1)", "1.0.1 (patch 12.9.1)", "1.0.0"]
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::{
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+use super::{
+    http,
+    mirror::{self, MirrorPool, Resource},
+};
+
+const CACHE_DIR: &str = "champr";
+
+/// Entries not used for this long are evicted.
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Past this size the least recently used entries are evicted, pinned
+/// source tarballs add up quickly.
+pub const MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Validators of a cached response, stored next to its body.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CacheMeta {
+    pub key: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// SHA-256 of the body, so a body is never paired with the validators
+    /// of another response.
+    pub body_hash: String,
+}
+
+/// Responses kept on disk, one `{hash}.json` and `{hash}.body` pair per key.
+///
+/// Keys are `Resource:path` for mirrored resources instead of the URL: the
+/// same file is served by every mirror, and keying by URL would cache it
+/// once per mirror and miss whenever the pool fails over. Absolute URLs
+/// that belong to no mirror are their own key.
+pub struct HttpCache {
+    dir: PathBuf,
+    max_age: Duration,
+    max_bytes: u64,
+}
+
+pub fn default_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(CACHE_DIR)
+        .join("http")
+}
+
+fn body_hash(body: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(body))
+}
+
+/// Writes to a temporary file first, so `p` is either the old or the new
+/// content, never half of it.
+fn write_atomic(p: &Path, buf: &[u8]) -> Result<()> {
+    let mut tmp = p.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", process::id()));
+    fs::write(&tmp, buf)?;
+    fs::rename(&tmp, p)?;
+    Ok(())
+}
+
+fn header(resp: &Response, name: reqwest::header::HeaderName) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+impl HttpCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            max_age: MAX_AGE,
+            max_bytes: MAX_BYTES,
+        }
+    }
+
+    pub fn with_limits(dir: PathBuf, max_age: Duration, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_age,
+            max_bytes,
+        }
+    }
+
+    fn paths(&self, key: &str) -> (PathBuf, PathBuf) {
+        let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+        (
+            self.dir.join(format!("{}.json", hash)),
+            self.dir.join(format!("{}.body", hash)),
+        )
+    }
+
+    /// The cached response for `key`, if both halves are readable.
+    pub fn load(&self, key: &str) -> Option<(CacheMeta, Vec<u8>)> {
+        let (meta_path, body_path) = self.paths(key);
+        let meta: CacheMeta = serde_json::from_str(&fs::read_to_string(meta_path).ok()?).ok()?;
+        if meta.key != key {
+            return None;
+        }
+        let body = fs::read(body_path).ok()?;
+        if meta.body_hash != body_hash(&body) {
+            return None;
+        }
+        Some((meta, body))
+    }
+
+    /// Stores a response, `meta.body_hash` is filled in here. Evicts old
+    /// entries afterwards.
+    pub fn store(&self, meta: &CacheMeta, body: &[u8]) -> Result<()> {
+        let (meta_path, body_path) = self.paths(&meta.key);
+        fs::create_dir_all(&self.dir)?;
+        let meta = CacheMeta {
+            body_hash: body_hash(body),
+            ..meta.clone()
+        };
+        write_atomic(&body_path, body)?;
+        write_atomic(&meta_path, serde_json::to_string(&meta)?.as_bytes())?;
+        self.evict()
+    }
+
+    /// Marks the entry of `key` as used, eviction goes by last use.
+    fn touch(&self, meta: &CacheMeta) {
+        let (meta_path, _) = self.paths(&meta.key);
+        let ret = serde_json::to_string(meta)
+            .map_err(anyhow::Error::from)
+            .and_then(|buf| write_atomic(&meta_path, buf.as_bytes()));
+        if let Err(e) = ret {
+            println!("[cache] failed to touch {}, {:?}", meta.key, e);
+        }
+    }
+
+    /// Drops entries unused for longer than the max age, then the least
+    /// recently used ones until the cache fits its size limit.
+    pub fn evict(&self) -> Result<()> {
+        let now = SystemTime::now();
+        let mut entries = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let p = entry?.path();
+            if p.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let body = p.with_extension("body");
+            let used = fs::metadata(&p)?.modified()?;
+            let size = fs::metadata(&body).map(|m| m.len()).unwrap_or(0);
+            entries.push((used, size, p, body));
+        }
+        // most recently used first
+        entries.sort_by_key(|e| std::cmp::Reverse(e.0));
+
+        let mut total = 0;
+        for (used, size, meta_path, body_path) in entries.into_iter() {
+            let age = now.duration_since(used).unwrap_or_default();
+            if age <= self.max_age && total + size <= self.max_bytes {
+                total += size;
+                continue;
+            }
+            let _ = fs::remove_file(&meta_path);
+            let _ = fs::remove_file(&body_path);
+        }
+        Ok(())
+    }
+
+    /// Sends the request built by `send` with the validators of the cached
+    /// copy of `key`. A 304 or a failed request is answered from the cache,
+    /// a fresh 2xx body replaces it.
+    pub async fn fetch<F, Fut>(&self, key: &str, send: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce(HeaderMap) -> Fut,
+        Fut: Future<Output = Result<Response>>,
+    {
+        let cached = self.load(key);
+        let mut headers = HeaderMap::new();
+        if let Some((meta, _)) = cached.as_ref() {
+            if let Some(v) = meta
+                .etag
+                .as_ref()
+                .and_then(|v| HeaderValue::from_str(v).ok())
+            {
+                headers.insert(IF_NONE_MATCH, v);
+            }
+            if let Some(v) = meta
+                .last_modified
+                .as_ref()
+                .and_then(|v| HeaderValue::from_str(v).ok())
+            {
+                headers.insert(IF_MODIFIED_SINCE, v);
+            }
+        }
+
+        let resp = match send(headers).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                return match cached {
+                    Some((_, body)) => {
+                        println!("[cache] {} unreachable, using cached copy, {:?}", key, e);
+                        Ok(body)
+                    }
+                    None => Err(e),
+                }
+            }
+        };
+
+        let status = resp.status();
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some((meta, body)) = cached {
+                self.touch(&meta);
+                return Ok(body);
+            }
+        }
+        if status.is_server_error() {
+            if let Some((_, body)) = cached {
+                println!("[cache] {} {}, using cached copy", key, status);
+                return Ok(body);
+            }
+        }
+        if !status.is_success() {
+            return Err(Error::HttpStatus {
+                url: key.to_string(),
+                status: status.as_u16(),
+            }
+            .into());
+        }
+
+        let meta = CacheMeta {
+            key: key.to_string(),
+            etag: header(&resp, ETAG),
+            last_modified: header(&resp, LAST_MODIFIED),
+            ..CacheMeta::default()
+        };
+        let body = http::read_body(resp, http::retry_policy().read_timeout).await?;
+        if let Err(e) = self.store(&meta, &body) {
+            println!("[cache] failed to store {}, {:?}", key, e);
+        }
+        Ok(body)
+    }
+
+    /// GETs `path` through `pool`, keyed by resource and path.
+    pub async fn get(&self, pool: &MirrorPool, resource: Resource, path: &str) -> Result<Vec<u8>> {
+        let key = format!("{:?}:{}", resource, path);
+        self.fetch(&key, |headers| {
+            pool.get_with_headers(resource, path, headers)
+        })
+        .await
+    }
+}
+
+/// Body of `path` from the mirrors of `resource`, revalidated against the
+/// on-disk cache.
+pub async fn get(resource: Resource, path: &str) -> Result<Vec<u8>> {
+    let pool = mirror::pool()?;
+    HttpCache::new(default_dir())
+        .get(&pool, resource, path)
+        .await
+}
+
+/// Body of an absolute URL that belongs to no mirror.
+pub async fn get_url(url: &str) -> Result<Vec<u8>> {
+    let client = http::client()?;
+    HttpCache::new(default_dir())
+        .fetch(url, |headers| {
+            http::get_with_headers(&client, url, headers, http::retry_policy())
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_dir, Response as StubResponse, StubServer, TempDir};
+
+    fn make_pool(url: String) -> MirrorPool {
+        let client = http::build_client(&http::HttpSettings::default()).unwrap();
+        let policy = http::RetryPolicy {
+            retries: 0,
+            ..http::RetryPolicy::default()
+        };
+        let mirrors = mirror::Mirrors {
+            ddragon: vec![url],
+            ..mirror::Mirrors::default()
+        };
+        MirrorPool::with_client(mirrors, client, policy)
+    }
+
+    /// The cache and the folder it lives in, removed when the guard drops.
+    fn make_cache(name: &str) -> (HttpCache, TempDir) {
+        let dir = temp_dir(name);
+        (HttpCache::new(dir.to_path_buf()), dir)
+    }
+
+    #[tokio::test]
+    async fn revalidate_with_etag() {
+        let server =
+            StubServer::http(
+                |req| match (req.path.as_str(), req.headers.get("if-none-match")) {
+                    ("/api/versions.json", Some(etag)) if etag == "\"v1\"" => {
+                        StubResponse::status(304)
+                    }
+                    ("/api/versions.json", _) => StubResponse::json(200, r#"["12.10.1"]"#)
+                        .with_header("ETag", "\"v1\"")
+                        .with_header("Last-Modified", "Wed, 18 May 2022 08:00:00 GMT"),
+                    _ => StubResponse::status(404),
+                },
+            )
+            .await;
+        let pool = make_pool(server.url());
+        let (cache, _dir) = make_cache("champr_cache_etag");
+
+        for _ in 0..2 {
+            let body = cache
+                .get(&pool, Resource::Ddragon, "/api/versions.json")
+                .await
+                .unwrap();
+            assert_eq!(body, br#"["12.10.1"]"#);
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].headers.contains_key("if-none-match"));
+        assert_eq!(requests[1].headers["if-none-match"], "\"v1\"");
+        assert_eq!(
+            requests[1].headers["if-modified-since"],
+            "Wed, 18 May 2022 08:00:00 GMT"
+        );
+
+        // errors are not cached
+        let err = cache
+            .get(&pool, Resource::Ddragon, "/missing")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            crate::error::classify(&err),
+            Error::HttpStatus {
+                url: "Ddragon:/missing".to_string(),
+                status: 404
+            }
+        );
+        assert!(cache.load("Ddragon:/missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn serve_cached_when_offline() {
+        let (cache, _dir) = make_cache("champr_cache_offline");
+        let server = StubServer::http(|_| StubResponse::json(200, r#"["12.10.1"]"#)).await;
+        cache
+            .get(
+                &make_pool(server.url()),
+                Resource::Ddragon,
+                "/api/versions.json",
+            )
+            .await
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let down = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        drop(listener);
+        let offline = make_pool(down);
+
+        let body = cache
+            .get(&offline, Resource::Ddragon, "/api/versions.json")
+            .await
+            .unwrap();
+        assert_eq!(body, br#"["12.10.1"]"#);
+        assert!(cache
+            .get(
+                &offline,
+                Resource::Ddragon,
+                "/cdn/12.10.1/data/en_US/champion.json"
+            )
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn evict_and_verify() {
+        let dir = temp_dir("champr_cache_evict");
+        let cache = HttpCache::with_limits(dir.to_path_buf(), MAX_AGE, 10);
+        let meta = |key: &str| CacheMeta {
+            key: key.to_string(),
+            ..CacheMeta::default()
+        };
+
+        cache.store(&meta("a"), b"123456").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        cache.store(&meta("b"), b"123456").unwrap();
+        // over the size limit, the older entry goes
+        assert!(cache.load("a").is_none());
+        assert_eq!(cache.load("b").unwrap().1, b"123456");
+
+        // a body that doesn't match its meta is a miss
+        let (_, body_path) = cache.paths("b");
+        fs::write(body_path, b"654321").unwrap();
+        assert!(cache.load("b").is_none());
+
+        let expired = HttpCache::with_limits(dir.to_path_buf(), Duration::ZERO, MAX_BYTES);
+        expired.store(&meta("c"), b"1").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        expired.evict().unwrap();
+        assert!(expired.load("c").is_none());
+    }
+}
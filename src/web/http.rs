@@ -0,0 +1,290 @@
+use std::{
+    sync::{PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::{header::HeaderMap, Client, Proxy, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+const USER_AGENT: &str = concat!("ChampR.rs/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Network settings shared by every download.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpSettings {
+    /// `http://`, `https://` or `socks5://` proxy URL. Empty falls back to
+    /// the `HTTP(S)_PROXY` environment variables.
+    pub proxy: String,
+    /// How long a request may wait for the response or for the next chunk
+    /// of its body. Large downloads aren't capped as long as data flows.
+    pub timeout_secs: u64,
+    /// Extra attempts on timeouts and transient server errors.
+    pub retries: u32,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            proxy: String::new(),
+            timeout_secs: 30,
+            retries: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub base_delay: Duration,
+    /// See `HttpSettings::timeout_secs`.
+    pub read_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: HttpSettings::default().retries,
+            base_delay: Duration::from_millis(500),
+            read_timeout: Duration::from_secs(HttpSettings::default().timeout_secs),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt`, doubling each time.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        delay.min(MAX_BACKOFF)
+    }
+}
+
+pub fn build_client(settings: &HttpSettings) -> Result<Client> {
+    // HTTP/2 is negotiated through ALPN (`native-tls-alpn`) wherever the
+    // server offers it. No total timeout, `read_body` times out idle reads
+    // instead so tarballs on slow links can finish.
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .gzip(true)
+        .brotli(true);
+    if !settings.proxy.is_empty() {
+        builder = builder.proxy(Proxy::all(&settings.proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+lazy_static! {
+    static ref SHARED: RwLock<Option<(Client, RetryPolicy)>> = RwLock::new(None);
+}
+
+/// Rebuilds the shared client. Mirrors have to be set again afterwards to
+/// pick it up.
+pub fn configure(settings: &HttpSettings) -> Result<()> {
+    let client = build_client(settings)?;
+    let policy = RetryPolicy {
+        retries: settings.retries,
+        read_timeout: Duration::from_secs(settings.timeout_secs.max(1)),
+        ..RetryPolicy::default()
+    };
+    *SHARED.write().unwrap_or_else(PoisonError::into_inner) = Some((client, policy));
+    Ok(())
+}
+
+fn shared() -> Result<(Client, RetryPolicy)> {
+    if let Some(shared) = SHARED
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+    {
+        return Ok(shared.clone());
+    }
+    configure(&HttpSettings::default())?;
+    SHARED
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .ok_or_else(|| anyhow!("http client not configured"))
+}
+
+/// The client every fetcher in `web` goes through, so connections are
+/// reused across the whole apply.
+pub fn client() -> Result<Client> {
+    Ok(shared()?.0)
+}
+
+pub fn retry_policy() -> RetryPolicy {
+    shared().map(|s| s.1).unwrap_or_default()
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::BAD_GATEWAY
+        || status == StatusCode::SERVICE_UNAVAILABLE
+        || status == StatusCode::GATEWAY_TIMEOUT
+}
+
+fn is_transient_error(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect()
+}
+
+/// GETs `url`, retrying with exponential backoff on timeouts, dropped
+/// connections and 429/502/503/504. The last response or error is returned
+/// once retries run out.
+pub async fn get_with_retry(client: &Client, url: &str, policy: RetryPolicy) -> Result<Response> {
+    get_with_headers(client, url, HeaderMap::new(), policy).await
+}
+
+/// Same as `get_with_retry`, sending `headers` with every attempt.
+pub async fn get_with_headers(
+    client: &Client,
+    url: &str,
+    headers: HeaderMap,
+    policy: RetryPolicy,
+) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let send = client.get(url).headers(headers.clone()).send();
+        let ret = tokio::time::timeout(policy.read_timeout, send).await;
+        let retry = match &ret {
+            Ok(Ok(resp)) => is_transient_status(resp.status()),
+            Ok(Err(e)) => is_transient_error(e),
+            Err(_) => true,
+        };
+        if !retry || attempt >= policy.retries {
+            return match ret {
+                Ok(ret) => Ok(ret?),
+                Err(_) => Err(anyhow!("{} timed out after {:?}", url, policy.read_timeout)),
+            };
+        }
+
+        let delay = policy.backoff(attempt);
+        println!(
+            "[http] {} failed after {:?}, retry in {:?}",
+            url,
+            started.elapsed(),
+            delay
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Reads the whole body of `resp`, failing once no data arrived for
+/// `read_timeout`.
+pub async fn read_body(mut resp: Response, read_timeout: Duration) -> Result<Vec<u8>> {
+    let url = resp.url().to_string();
+    let mut body = vec![];
+    loop {
+        let chunk = tokio::time::timeout(read_timeout, resp.chunk())
+            .await
+            .map_err(|_| anyhow!("{} stalled for {:?}", url, read_timeout))??;
+        match chunk {
+            Some(chunk) => body.extend_from_slice(&chunk),
+            None => return Ok(body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Response as StubResponse, StubServer};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn fast_policy(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn retry_transient_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let server = StubServer::http(move |req| {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            match (req.path.as_str(), n) {
+                ("/flaky", 0) | ("/flaky", 1) => StubResponse::status(503),
+                ("/flaky", _) => StubResponse::json(200, "[]"),
+                _ => StubResponse::status(404),
+            }
+        })
+        .await;
+        let client = build_client(&HttpSettings::default()).unwrap();
+
+        let url = format!("{}/flaky", server.url());
+        let resp = get_with_retry(&client, &url, fast_policy(2)).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // a 404 is final
+        let url = format!("{}/missing", server.url());
+        let resp = get_with_retry(&client, &url, fast_policy(2)).await.unwrap();
+        assert_eq!(resp.status(), 404);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_retries() {
+        let server = StubServer::http(|_| StubResponse::status(503)).await;
+        let client = build_client(&HttpSettings::default()).unwrap();
+
+        let resp = get_with_retry(&client, &server.url(), fast_policy(1))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 503);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn time_out_silent_server() {
+        // accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        let client = build_client(&HttpSettings::default()).unwrap();
+        let policy = RetryPolicy {
+            read_timeout: Duration::from_millis(100),
+            ..fast_policy(1)
+        };
+
+        let started = Instant::now();
+        assert!(get_with_retry(&client, &url, policy).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn reject_bad_proxy() {
+        let settings = HttpSettings {
+            proxy: "not a url".to_string(),
+            ..HttpSettings::default()
+        };
+        assert!(build_client(&settings).is_err());
+    }
+}
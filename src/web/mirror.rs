@@ -0,0 +1,361 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::{header::HeaderMap, Client, Response};
+use serde::{Deserialize, Serialize};
+
+use super::http::{self, RetryPolicy};
+
+/// Kinds of resources ChampR downloads, each served by its own mirrors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Resource {
+    /// The source list repository on GitHub.
+    SourceList,
+    /// Files inside published npm packages.
+    Npm,
+    /// npm registry metadata and tarballs.
+    Registry,
+    /// Riot's DDragon.
+    Ddragon,
+}
+
+/// Base URLs per resource, tried in order until one answers. A resource path
+/// such as `/api/versions.json` is appended to the base as is, so a
+/// self-hosted mirror only has to serve the same layout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Mirrors {
+    pub source_list: Vec<String>,
+    pub npm: Vec<String>,
+    pub registry: Vec<String>,
+    pub ddragon: Vec<String>,
+    /// The fastest mirror seen per resource, tried first on the next start.
+    pub preferred: BTreeMap<Resource, String>,
+}
+
+impl Default for Mirrors {
+    fn default() -> Self {
+        Self {
+            source_list: vec![
+                format!("{}/gh/champ-r/source-list", super::CDN_JSDELIVR),
+                "https://fastly.jsdelivr.net/gh/champ-r/source-list".to_string(),
+                "https://raw.githubusercontent.com/champ-r/source-list/master".to_string(),
+            ],
+            npm: vec![
+                format!("{}/npm", super::CDN_JSDELIVR),
+                "https://unpkg.com".to_string(),
+            ],
+            registry: vec![
+                super::NPM_MIRROR.to_string(),
+                "https://registry.npmjs.org".to_string(),
+            ],
+            ddragon: vec![super::CDN_DDRAGON.to_string()],
+            preferred: BTreeMap::new(),
+        }
+    }
+}
+
+impl Mirrors {
+    pub const RESOURCES: [Resource; 4] = [
+        Resource::SourceList,
+        Resource::Npm,
+        Resource::Registry,
+        Resource::Ddragon,
+    ];
+
+    pub fn urls(&self, resource: Resource) -> &[String] {
+        match resource {
+            Resource::SourceList => &self.source_list,
+            Resource::Npm => &self.npm,
+            Resource::Registry => &self.registry,
+            Resource::Ddragon => &self.ddragon,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Health {
+    Ok(Duration),
+    Failed,
+}
+
+/// Mirrors plus what was learned about them while running: the fastest
+/// mirror that answered is tried first, failed ones last. Until one has
+/// answered, the preferred mirror from the last run goes first.
+pub struct MirrorPool {
+    mirrors: Mirrors,
+    client: Client,
+    policy: RetryPolicy,
+    health: Mutex<HashMap<String, Health>>,
+}
+
+impl MirrorPool {
+    /// Uses the shared client from `http`.
+    pub fn new(mirrors: Mirrors) -> Result<Self> {
+        Ok(Self::with_client(
+            mirrors,
+            http::client()?,
+            http::retry_policy(),
+        ))
+    }
+
+    pub fn with_client(mirrors: Mirrors, client: Client, policy: RetryPolicy) -> Self {
+        let health = mirrors
+            .preferred
+            .values()
+            .map(|url| (url.clone(), Health::Ok(Duration::ZERO)))
+            .collect();
+        Self {
+            mirrors,
+            client,
+            policy,
+            health: Mutex::new(health),
+        }
+    }
+
+    pub fn mirrors(&self) -> &Mirrors {
+        &self.mirrors
+    }
+
+    /// Mirrors of `resource` in the order they will be tried.
+    pub fn ordered(&self, resource: Resource) -> Vec<String> {
+        let health = self.health.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut urls = self.mirrors.urls(resource).to_vec();
+        // stable sort: untried mirrors keep their configured order
+        urls.sort_by_key(|url| match health.get(url) {
+            Some(Health::Ok(elapsed)) => (0, *elapsed),
+            None => (1, Duration::ZERO),
+            Some(Health::Failed) => (2, Duration::ZERO),
+        });
+        urls
+    }
+
+    fn record(&self, base: &str, health: Health) {
+        self.health
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(base.to_string(), health);
+    }
+
+    /// The mirror of each resource that answered fastest, for
+    /// `Mirrors::preferred`. A preferred mirror not tried yet stays, one
+    /// that failed is dropped.
+    pub fn preferred(&self) -> BTreeMap<Resource, String> {
+        let mut preferred = BTreeMap::new();
+        for resource in Mirrors::RESOURCES {
+            if let Some(url) = self.ordered(resource).into_iter().next() {
+                let health = self
+                    .health
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get(&url)
+                    .copied();
+                if let Some(Health::Ok(_)) = health {
+                    preferred.insert(resource, url);
+                }
+            }
+        }
+        preferred
+    }
+
+    /// Splits an absolute URL served by one of the mirrors of `resource`
+    /// into its path, so it can be fetched from the others too.
+    pub fn strip_base(&self, resource: Resource, url: &str) -> Option<String> {
+        self.mirrors
+            .urls(resource)
+            .iter()
+            .find_map(|base| url.strip_prefix(base.trim_end_matches('/')))
+            .map(|path| path.to_string())
+    }
+
+    /// GETs `path` from the mirrors of `resource`, moving on to the next one
+    /// on connection errors, timeouts and 5xx answers once a mirror's
+    /// retries are used up.
+    pub async fn get(&self, resource: Resource, path: &str) -> Result<Response> {
+        self.get_with_headers(resource, path, HeaderMap::new())
+            .await
+    }
+
+    pub async fn get_with_headers(
+        &self,
+        resource: Resource,
+        path: &str,
+        headers: HeaderMap,
+    ) -> Result<Response> {
+        let mut last_err = anyhow!("no mirror configured for {:?}", resource);
+        for base in self.ordered(resource).iter() {
+            let url = format!("{}{}", base.trim_end_matches('/'), path);
+            let started = Instant::now();
+            match http::get_with_headers(&self.client, &url, headers.clone(), self.policy).await {
+                Ok(resp) if resp.status().is_server_error() => {
+                    println!("[mirror] {} {}, trying next", url, resp.status());
+                    last_err = anyhow!("{} {}", url, resp.status());
+                    self.record(base, Health::Failed);
+                }
+                Ok(resp) => {
+                    self.record(base, Health::Ok(started.elapsed()));
+                    return Ok(resp);
+                }
+                Err(e) => {
+                    println!("[mirror] {} failed, trying next, {:?}", url, e);
+                    last_err = e;
+                    self.record(base, Health::Failed);
+                }
+            }
+        }
+        Err(last_err)
+    }
+}
+
+lazy_static! {
+    static ref POOL: RwLock<Option<Arc<MirrorPool>>> = RwLock::new(None);
+}
+
+/// Replaces the mirrors used by every fetcher in `web`.
+pub fn set_mirrors(mirrors: Mirrors) -> Result<()> {
+    let pool = MirrorPool::new(mirrors)?;
+    *POOL.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(pool));
+    Ok(())
+}
+
+pub fn pool() -> Result<Arc<MirrorPool>> {
+    if let Some(pool) = POOL.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+        return Ok(pool.clone());
+    }
+    let pool = Arc::new(MirrorPool::new(Mirrors::default())?);
+    *POOL.write().unwrap_or_else(PoisonError::into_inner) = Some(pool.clone());
+    Ok(pool)
+}
+
+pub async fn get(resource: Resource, path: &str) -> Result<Response> {
+    pool()?.get(resource, path).await
+}
+
+/// `mirrors` with the fastest mirrors seen so far as preferred ones.
+pub fn remember_fastest(mirrors: &Mirrors) -> Mirrors {
+    let preferred = match POOL.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+        Some(pool) => pool.preferred(),
+        None => return mirrors.clone(),
+    };
+    Mirrors {
+        preferred,
+        ..mirrors.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Response as StubResponse, StubServer};
+
+    fn make_pool(mirrors: Mirrors) -> MirrorPool {
+        let client = http::build_client(&http::HttpSettings::default()).unwrap();
+        let policy = RetryPolicy {
+            retries: 0,
+            ..RetryPolicy::default()
+        };
+        MirrorPool::with_client(mirrors, client, policy)
+    }
+
+    /// A base URL nothing listens on.
+    async fn closed_url() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    #[tokio::test]
+    async fn fail_over_to_next_mirror() {
+        let broken = StubServer::http(|_| StubResponse::status(503)).await;
+        let good = StubServer::http(|req| match req.path.as_str() {
+            "/cdn/api/versions.json" => StubResponse::json(200, r#"["12.10.1"]"#),
+            _ => StubResponse::status(404),
+        })
+        .await;
+        let down = closed_url().await;
+        let good_url = format!("{}/cdn", good.url());
+
+        let pool = make_pool(Mirrors {
+            ddragon: vec![down.clone(), broken.url(), good_url.clone()],
+            ..Mirrors::default()
+        });
+
+        let resp = pool
+            .get(Resource::Ddragon, "/api/versions.json")
+            .await
+            .unwrap();
+        assert_eq!(resp.json::<Vec<String>>().await.unwrap(), vec!["12.10.1"]);
+        assert_eq!(broken.requests().len(), 1);
+
+        // the mirror that answered is remembered and tried first
+        assert_eq!(pool.ordered(Resource::Ddragon)[0], good_url);
+        pool.get(Resource::Ddragon, "/api/versions.json")
+            .await
+            .unwrap();
+        assert_eq!(broken.requests().len(), 1);
+
+        // a 404 is an answer, not a reason to fail over
+        let resp = pool.get(Resource::Ddragon, "/missing").await.unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn all_mirrors_down() {
+        let pool = make_pool(Mirrors {
+            registry: vec![closed_url().await],
+            ..Mirrors::default()
+        });
+        assert!(pool
+            .get(Resource::Registry, "/@champ-r/op.gg")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn strip_registry_base() {
+        let pool = make_pool(Mirrors::default());
+        assert_eq!(
+            pool.strip_base(
+                Resource::Registry,
+                "https://registry.npmjs.org/@champ-r/op.gg/-/op.gg-1.0.2.tgz"
+            ),
+            Some("/@champ-r/op.gg/-/op.gg-1.0.2.tgz".to_string())
+        );
+        assert_eq!(
+            pool.strip_base(Resource::Registry, "https://example.com/op.gg-1.0.2.tgz"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn seed_with_preferred_mirror() {
+        let first = StubServer::http(|_| StubResponse::json(200, "[]")).await;
+        let second = StubServer::http(|_| StubResponse::json(200, "[]")).await;
+        let mut mirrors = Mirrors {
+            ddragon: vec![first.url(), second.url()],
+            ..Mirrors::default()
+        };
+        mirrors.preferred.insert(Resource::Ddragon, second.url());
+
+        let pool = MirrorPool::new(mirrors).unwrap();
+        assert_eq!(pool.ordered(Resource::Ddragon)[0], second.url());
+        pool.get(Resource::Ddragon, "/api/versions.json")
+            .await
+            .unwrap();
+        assert_eq!(first.requests().len(), 0);
+        assert_eq!(
+            pool.preferred().get(&Resource::Ddragon),
+            Some(&second.url())
+        );
+
+        // a preferred mirror that failed is forgotten
+        pool.record(&second.url(), Health::Failed);
+        assert_eq!(pool.preferred().get(&Resource::Ddragon), None);
+    }
+}